@@ -1,16 +1,51 @@
 use anyhow::{Context, Result};
-use chrono::{DateTime, Utc};
-use clap::{Parser, Subcommand};
-use log::{error, info};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use chrono_tz::Tz;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use log::{debug, error, info, warn};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
-use std::path::Path;
+use std::hash::{Hash, Hasher};
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use unicode_normalization::UnicodeNormalization;
 
 #[derive(Parser)]
 #[command(name = "alts")]
 #[command(about = "Minimalistic version control software that simply saves copies of each version", long_about = None)]
+#[command(after_help = EXIT_CODES_HELP)]
 struct Cli {
+    /// Time zone for displaying timestamps: `local`, `utc` or an IANA name
+    /// like `America/New_York`. Defaults to the stored offset.
+    #[arg(long, global = true, value_parser = parse_time_zone)]
+    time_zone: Option<TimeZoneArg>,
+
+    /// Number of worker threads for copying and hashing (defaults to the CPU count)
+    #[arg(short, long, global = true)]
+    jobs: Option<usize>,
+
+    /// Directory containing `.alts` (defaults to the current directory).
+    /// The tracked directory is resolved against it too.
+    #[arg(long, global = true, value_name = "PATH")]
+    repo: Option<PathBuf>,
+
+    /// Only log errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also log debug details
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -21,47 +56,1079 @@ enum Commands {
     Init {
         /// The directory name to track
         dir_name: String,
+        /// strftime pattern used when generating checkpoint names
+        #[arg(long, value_name = "FORMAT")]
+        timestamp_format: Option<String>,
+        /// Allow tracking a directory outside the current directory; it is
+        /// recorded as an absolute path
+        #[arg(long)]
+        allow_outside: bool,
+        /// Create the repository even inside another alts repository
+        #[arg(long)]
+        nested: bool,
+        /// Create a first checkpoint right away, optionally with this name
+        #[arg(long, value_name = "NAME", num_args = 0..=1)]
+        checkpoint: Option<Option<String>>,
     },
     /// Create a checkpoint (alias: ck)
     #[command(alias = "ck")]
-    Checkpoint {
-        /// Optional checkpoint name
-        name: Option<String>,
-    },
+    Checkpoint(CheckpointArgs),
     /// List all checkpoints (alias: ls)
     #[command(alias = "ls")]
-    List {
-        /// List in time order
-        #[arg(short = 't', long = "time")]
-        time_order: bool,
-        /// Reverse the order
-        #[arg(short = 'r', long = "reverse")]
-        reverse: bool,
-    },
+    List(ListArgs),
     /// Remove unfound checkpoints from index
-    Prune,
+    Prune(PruneArgs),
+    /// Delete checkpoints and their files (alias: rm)
+    #[command(alias = "rm")]
+    Delete {
+        /// Checkpoint names; on a terminal, asks for one when none is given
+        #[arg(conflicts_with = "SelectArgs")]
+        names: Vec<String>,
+        /// Delete without asking, even past the configured confirmation thresholds
+        #[arg(long)]
+        force: bool,
+        #[command(flatten)]
+        select: SelectArgs,
+    },
+    /// Rename a checkpoint, keeping its timestamp
+    Rename {
+        /// Current checkpoint name
+        old: String,
+        /// New checkpoint name
+        new: String,
+    },
     /// Show repository metadata
-    Info,
+    Info {
+        /// Unit used to display sizes
+        #[arg(long, value_enum, default_value_t = SizeUnit::Auto)]
+        unit: SizeUnit,
+        /// Redraw the report periodically until interrupted
+        #[arg(long, conflicts_with = "max_age_warn")]
+        watch: bool,
+        /// Seconds between redraws with --watch
+        #[arg(long, default_value_t = 5, requires = "watch")]
+        interval: u64,
+        /// Print the report as a JSON object with sizes in bytes; only
+        /// warnings and errors are logged
+        #[arg(long, conflicts_with = "watch")]
+        json: bool,
+        #[command(flatten)]
+        age: AgeCheckArgs,
+    },
+    /// Watch the target directory and checkpoint automatically on change
+    Watch {
+        /// Seconds between scans
+        #[arg(long, default_value_t = 5)]
+        interval: u64,
+        /// Shell command to run after each automatic checkpoint; the new
+        /// checkpoint name is available as $ALTS_CHECKPOINT
+        #[arg(long, value_name = "COMMAND")]
+        on_change: Option<String>,
+    },
+    /// Restore the target directory from a checkpoint
+    Restore {
+        /// The checkpoint name; on a terminal, asks for one when omitted
+        name: Option<String>,
+        /// Only write files that differ from the target, preserving unchanged ones
+        #[arg(long)]
+        only_changed: bool,
+        /// Hash both trees afterwards and fail if the target does not match
+        #[arg(long)]
+        verify_after: bool,
+        /// Set the mtime of every restored file to the checkpoint's creation time
+        #[arg(long)]
+        to_checkpoint_time: bool,
+        /// Extract into this directory instead of the target, creating it if
+        /// needed
+        #[arg(long, value_name = "DIR", conflicts_with = "only_changed")]
+        into: Option<PathBuf>,
+        /// With --into, write into a directory that is not empty
+        #[arg(long, requires = "into")]
+        force: bool,
+    },
+    /// Show which files restoring a checkpoint would create, overwrite or delete
+    Impact {
+        /// The checkpoint name
+        name: String,
+        /// Unit used to display sizes
+        #[arg(long, value_enum, default_value_t = SizeUnit::Auto)]
+        unit: SizeUnit,
+    },
+    /// List files added, modified or deleted relative to a checkpoint; without
+    /// a second checkpoint, compares against the target directory
+    Diff {
+        /// The checkpoint name
+        name: String,
+        /// Checkpoint to compare against
+        other: Option<String>,
+        /// Directory to compare the checkpoint against
+        #[arg(long, value_name = "PATH", conflicts_with = "other")]
+        external: Option<PathBuf>,
+        /// Stable, script-friendly output
+        #[arg(
+            long,
+            long_help = "Stable, script-friendly output. Prints one line per change as \
+                         `<change>\\t<path>` where change is `added`, `modified` or \
+                         `deleted`. Backslashes, tabs and line breaks in paths are escaped \
+                         as `\\\\`, `\\t`, `\\n` and `\\r`. Nothing else goes to stdout."
+        )]
+        porcelain: bool,
+    },
+    /// Run a command and checkpoint the target if it succeeds
+    Run {
+        /// Checkpoint when the command fails instead
+        #[arg(long)]
+        on_failure: bool,
+        /// Checkpoint name (generated by default)
+        #[arg(long)]
+        name: Option<String>,
+        /// Checkpoint message (defaults to the command line)
+        #[arg(short, long)]
+        message: Option<String>,
+        /// The command to run, usually after `--`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        cmd: Vec<String>,
+    },
+    /// Upgrade the repository config to the current schema
+    Migrate,
+    /// Check the index against the checkpoint directories without changing anything
+    Fsck,
+    /// Compare this repository's checkpoints with another repository's
+    CompareRepos {
+        /// Directory containing the other repository's .alts
+        other: String,
+    },
+    /// Manage movable labels for checkpoints
+    Alias {
+        #[command(subcommand)]
+        command: AliasCommands,
+    },
+    /// View and change repository settings in alts.toml
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Print the JSON Schema of a `--json` output
+    #[command(hide = true)]
+    Schema {
+        #[arg(value_enum)]
+        output: SchemaOutput,
+    },
+    /// Internal diagnostics
+    #[command(hide = true)]
+    Debug {
+        #[command(subcommand)]
+        command: DebugCommands,
+    },
+    /// Show detailed statistics for a single checkpoint
+    Stat {
+        /// The checkpoint name
+        name: String,
+        /// Unit used to display sizes
+        #[arg(long, value_enum, default_value_t = SizeUnit::Auto)]
+        unit: SizeUnit,
+    },
+    /// List what changed in the target since the most recent checkpoint
+    Status {
+        /// Stable, script-friendly output
+        #[arg(
+            long,
+            long_help = "Stable, script-friendly output. Prints one line per change as \
+                         `<change>\\t<path>` where change is `added`, `modified` or \
+                         `deleted`. Backslashes, tabs and line breaks in paths are escaped \
+                         as `\\\\`, `\\t`, `\\n` and `\\r`. Nothing else goes to stdout."
+        )]
+        porcelain: bool,
+    },
+    /// Show the history of checkpoint operations, newest first
+    Reflog,
+    /// Show checkpoints by creation time, newest first, with their messages
+    Log {
+        /// Show at most this many checkpoints
+        #[arg(short = 'n', long, value_name = "COUNT")]
+        max_count: Option<usize>,
+    },
+    /// Re-hash checkpoints and compare them with the checksums recorded at
+    /// creation; checks every checkpoint when no name is given
+    Verify {
+        /// The checkpoint name
+        name: Option<String>,
+    },
+    /// Fill in missing or invalid checkpoint timestamps from the modification
+    /// time of each checkpoint's data
+    Touch {
+        /// Checkpoints to consider
+        #[arg(required_unless_present = "all_valid", conflicts_with = "all_valid")]
+        names: Vec<String>,
+        /// Consider every checkpoint whose data is present
+        #[arg(long)]
+        all_valid: bool,
+        /// Also overwrite timestamps that are already valid
+        #[arg(long)]
+        force: bool,
+    },
+    /// Package a checkpoint and its metadata into a .tar.gz file
+    Export {
+        /// The checkpoint name
+        #[arg(required_unless_present = "to_dir", conflicts_with = "SelectArgs")]
+        name: Option<String>,
+        /// Archive to create
+        #[arg(required_unless_present = "to_dir")]
+        file: Option<PathBuf>,
+        /// Export every selected checkpoint to `<DIR>/<name>.tar.gz`
+        #[arg(
+            long,
+            value_name = "DIR",
+            requires = "SelectArgs",
+            conflicts_with_all = ["name", "file", "relative_to"]
+        )]
+        to_dir: Option<PathBuf>,
+        #[command(flatten)]
+        select: SelectArgs,
+        /// Only include what changed since this checkpoint, for importing
+        /// with `--onto` where it already exists
+        #[arg(long, value_name = "BASE")]
+        relative_to: Option<String>,
+        /// Write the archive as numbered parts (`<FILE>.001`, ...) of at most
+        /// this size, e.g. `650MB`, listed in `<FILE>.parts`
+        #[arg(long, value_name = "SIZE", value_parser = parse_part_size, conflicts_with = "to_dir")]
+        split: Option<u64>,
+    },
+    /// Add a checkpoint from a file written by `alts export`
+    Import {
+        /// Archive to read, or the `.parts` manifest of a split export
+        file: PathBuf,
+        /// If the name is taken, import under the first free `<name>_<n>`
+        #[arg(long)]
+        rename: bool,
+        /// Apply an archive written with `--relative-to` on top of this
+        /// checkpoint, which must match the exported base
+        #[arg(long, value_name = "BASE")]
+        onto: Option<String>,
+    },
+}
+
+#[derive(Args, Default)]
+struct CheckpointArgs {
+    /// Optional checkpoint name
+    name: Option<String>,
+    /// Name the checkpoint after a hash of its content; identical content is
+    /// stored only once
+    #[arg(long, conflicts_with = "name")]
+    content_name: bool,
+    /// Create the checkpoint by extracting a .tar, .tar.gz/.tgz or .zip archive
+    /// instead of copying the target directory
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["content_name", "git_tracked_only", "verify_sample", "verify"]
+    )]
+    from_archive: Option<PathBuf>,
+    /// Snapshot this directory under the repository root instead of the
+    /// target; restoring the checkpoint writes back to it
+    #[arg(long, value_name = "DIR", conflicts_with = "from_archive")]
+    from: Option<String>,
+    /// Copy the content of symlinks pointing outside the target directory
+    #[arg(long)]
+    snapshot_symlink_targets: bool,
+    /// Copy the content behind every symlink instead of recreating the link.
+    /// Links that would lead into a cycle are still recreated as links.
+    #[arg(long, short = 'L')]
+    dereference: bool,
+    /// Read gitignore-style exclude patterns from a file (repeatable)
+    #[arg(long, value_name = "FILE")]
+    exclude_from: Vec<PathBuf>,
+    /// strftime pattern used for the generated name, overriding the config
+    #[arg(long, value_name = "FORMAT")]
+    timestamp_format: Option<String>,
+    /// Print only the created checkpoint's name to stdout, suppressing logs
+    #[arg(long)]
+    output_name_only: bool,
+    /// Copy only the files `git ls-files` reports in the target
+    #[arg(long)]
+    git_tracked_only: bool,
+    /// Recreate hard links between files in the target instead of copying each
+    /// path separately (Unix only; ignored elsewhere)
+    #[arg(long)]
+    preserve_hardlinks: bool,
+    /// After copying, re-hash this percentage of files (chosen at random) and
+    /// compare them with the source, discarding the checkpoint on mismatch
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    verify_sample: Option<f64>,
+    /// Hash every file as it is read and check the written copy against it,
+    /// discarding the checkpoint on any mismatch
+    #[arg(long, conflicts_with = "verify_sample")]
+    verify: bool,
+    /// Leave out files and directories that cannot be read instead of
+    /// failing; the checkpoint is recorded as partial
+    #[arg(long, conflicts_with = "from_archive")]
+    skip_errors: bool,
+    /// Record the Unix epoch (or $SOURCE_DATE_EPOCH) as the creation time and
+    /// number generated names sequentially instead of using the clock
+    #[arg(long)]
+    no_timestamps: bool,
+    /// Also copy `.alts` directories found inside the target, which are
+    /// skipped by default
+    #[arg(long)]
+    include_dotalts: bool,
+    /// Describe the checkpoint; `-` reads the message from stdin
+    #[arg(short, long)]
+    message: Option<String>,
+    /// Attach a tag to the checkpoint (repeatable)
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Also tag the checkpoint with the target's current git branch
+    #[arg(long)]
+    tag_from_branch: bool,
+    /// Store files that also exist in this checkpoint as binary deltas against
+    /// it, whenever the delta is smaller than the file
+    #[arg(
+        long,
+        value_name = "BASE",
+        conflicts_with_all = ["content_name", "from_archive"]
+    )]
+    delta_against: Option<String>,
+    /// Copy every file even when `incremental` is set in the config
+    #[arg(long)]
+    full: bool,
+    /// Replace an existing checkpoint of the same name; the old one is only
+    /// removed once the new one is complete
+    #[arg(long, requires = "name")]
+    force: bool,
+    /// Report the planned name and how many files and bytes would be copied,
+    /// without writing anything
+    #[arg(long, conflicts_with_all = ["content_name", "from_archive"])]
+    dry_run: bool,
+    /// Periodically log a "copied X/Y files (Z%)" line, suited to log files
+    #[arg(long, conflicts_with = "from_archive")]
+    progress_lines: bool,
+    /// Minimum time between progress lines
+    #[arg(
+        long,
+        value_name = "MS",
+        requires = "progress_lines",
+        default_value_t = 100
+    )]
+    progress_interval: u64,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// List in time order
+    #[arg(short = 't', long = "time")]
+    time_order: bool,
+    /// Reverse the order
+    #[arg(short = 'r', long = "reverse")]
+    reverse: bool,
+    /// Show checkpoint sizes in the given unit
+    #[arg(long, value_enum)]
+    unit: Option<SizeUnit>,
+    /// Stable, script-friendly output
+    #[arg(
+        long,
+        long_help = "Stable, script-friendly output. Prints one line per checkpoint as \
+                     `<state>\\t<name>\\t<timestamp>\\t<tags>\\t<message>` where state \
+                     is `ok` or `missing` and tags are comma-separated. Backslashes, tabs \
+                     and line breaks in tags and messages are escaped as `\\\\`, `\\t`, \
+                     `\\n` and `\\r`, and commas in tags as `\\,`. Informational logs are \
+                     suppressed."
+    )]
+    porcelain: bool,
+    /// Group checkpoints under a heading per tag
+    #[arg(long, conflicts_with = "porcelain")]
+    group_by_tag: bool,
+    /// Print a JSON array of `{name, timestamp, exists}` objects; only
+    /// warnings and errors are logged
+    #[arg(long, conflicts_with_all = ["porcelain", "group_by_tag"])]
+    json: bool,
+    /// Show only checkpoints carrying this tag; repeat to require several
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Show only checkpoints whose data is missing from `.alts`
+    #[arg(long)]
+    missing: bool,
+    /// Show at most N checkpoints per page
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    per_page: Option<u64>,
+    /// Page to show, starting at 1
+    #[arg(
+        long,
+        value_name = "P",
+        requires = "per_page",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    page: u64,
+    #[command(flatten)]
+    age: AgeCheckArgs,
+}
+
+#[derive(Args)]
+struct AgeCheckArgs {
+    /// Warn when the newest checkpoint is older than this, e.g. `90m`, `12h`, `7d`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    max_age_warn: Option<Duration>,
+    /// Exit with an error instead of only warning when --max-age-warn is exceeded
+    #[arg(long, requires = "max_age_warn")]
+    strict: bool,
+}
+
+/// Filters choosing checkpoints for bulk commands; a checkpoint is selected
+/// when it passes every filter given
+#[derive(Args)]
+struct SelectArgs {
+    /// Select checkpoints whose name matches this glob, e.g. `release-*`
+    #[arg(long = "matching", value_name = "GLOB")]
+    glob: Option<String>,
+    /// Select checkpoints whose name starts with this
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Select checkpoints carrying this tag; repeat to require several
+    #[arg(long = "tag", value_name = "TAG")]
+    tags: Vec<String>,
+    /// Select checkpoints created before this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE")]
+    before: Option<NaiveDate>,
+    /// Select checkpoints created on or between these dates (YYYY-MM-DD)
+    #[arg(long, num_args = 2, value_names = ["FROM", "TO"])]
+    between: Option<Vec<NaiveDate>>,
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("previewable").args(["older_than", "orphans"])))]
+struct PruneArgs {
+    /// Keep only the newest checkpoint of each calendar day (in the
+    /// --time-zone, local by default), deleting the rest
+    #[arg(long, conflicts_with_all = ["dedup", "older_than"])]
+    keep_latest_per_day: bool,
+    /// Delete checkpoints created longer ago than this, e.g. `12h`, `30d`, `2w`
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, conflicts_with = "dedup")]
+    older_than: Option<Duration>,
+    /// Delete files and directories in .alts that no checkpoint in the index
+    /// owns, such as leftovers of failed or hand-copied checkpoints
+    #[arg(long, conflicts_with_all = ["keep_latest_per_day", "dedup", "older_than"])]
+    orphans: bool,
+    /// With --older-than or --orphans, only list what would be deleted
+    #[arg(long, requires = "previewable")]
+    dry_run: bool,
+    /// Delete checkpoints whose content is identical to another one, keeping
+    /// the newest of each group
+    #[arg(long)]
+    dedup: bool,
+    /// With --dedup, keep the oldest checkpoint of each group instead
+    #[arg(long, requires = "dedup")]
+    keep_oldest: bool,
+    /// With --dedup, turn each deleted name into an alias of the kept checkpoint
+    #[arg(long, requires = "dedup")]
+    alias_removed: bool,
+    /// Never delete this checkpoint (or alias), whatever the mode; repeatable
+    #[arg(long = "except", value_name = "NAME")]
+    except: Vec<String>,
+    /// Delete without asking, even past the configured confirmation thresholds
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Subcommand)]
+enum AliasCommands {
+    /// Point a label at a checkpoint, moving it if it already exists
+    Set {
+        /// The alias label
+        label: String,
+        /// The checkpoint it should refer to
+        checkpoint: String,
+    },
+    /// Remove a label
+    Rm {
+        /// The alias label
+        label: String,
+    },
+    /// List all labels (alias: ls)
+    #[command(alias = "ls")]
+    List,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the value of a setting
+    Get {
+        /// Setting name, e.g. `max_checkpoints` or `webhook.url`
+        key: String,
+    },
+    /// Validate and store a new value for a setting
+    Set {
+        /// Setting name, e.g. `max_checkpoints` or `webhook.url`
+        key: String,
+        /// New value; `exclude_always` takes one pattern per value
+        #[arg(required = true, allow_hyphen_values = true)]
+        values: Vec<String>,
+    },
+    /// Reset a setting to its default
+    Unset {
+        /// Setting name, e.g. `max_checkpoints` or `webhook.url`
+        key: String,
+    },
+    /// Print every setting that is set, as TOML
+    #[command(alias = "ls")]
+    List,
+}
+
+#[derive(Subcommand)]
+enum DebugCommands {
+    /// Compare the native size walker against `du` for a checkpoint
+    SizeCompare {
+        /// The checkpoint name
+        name: String,
+    },
+    /// Print the content hash of a checkpoint's tree
+    TreeHash {
+        /// The checkpoint name
+        name: String,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum TimeZoneArg {
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+fn parse_time_zone(s: &str) -> Result<TimeZoneArg, String> {
+    match s.to_lowercase().as_str() {
+        "local" => Ok(TimeZoneArg::Local),
+        "utc" => Ok(TimeZoneArg::Utc),
+        _ => s.parse::<Tz>().map(TimeZoneArg::Named).map_err(|_| {
+            format!(
+                "unknown time zone '{}'; use 'local', 'utc' or an IANA name like 'America/New_York'",
+                s
+            )
+        }),
+    }
+}
+
+impl TimeZoneArg {
+    /// Calendar date of an RFC3339 timestamp in this zone
+    fn date_of(self, timestamp: &str) -> Option<NaiveDate> {
+        let utc = DateTime::parse_from_rfc3339(timestamp)
+            .ok()?
+            .with_timezone(&Utc);
+        Some(match self {
+            TimeZoneArg::Local => utc.with_timezone(&Local).date_naive(),
+            TimeZoneArg::Utc => utc.date_naive(),
+            TimeZoneArg::Named(tz) => utc.with_timezone(&tz).date_naive(),
+        })
+    }
+}
+
+impl SelectArgs {
+    fn is_given(&self) -> bool {
+        self.glob.is_some()
+            || self.prefix.is_some()
+            || !self.tags.is_empty()
+            || self.before.is_some()
+            || self.between.is_some()
+    }
+}
+
+/// Names of the checkpoints passing every filter in `select`, oldest first,
+/// each logged with its timestamp. Dates are taken in `time_zone`, or in the
+/// offset stored with each timestamp; checkpoints without a valid timestamp
+/// never pass a date filter.
+fn select_checkpoints(
+    config: &Config,
+    select: &SelectArgs,
+    time_zone: Option<TimeZoneArg>,
+) -> Result<Vec<String>> {
+    let glob = select
+        .glob
+        .as_deref()
+        .map(|glob| {
+            globset::Glob::new(glob)
+                .map(|glob| glob.compile_matcher())
+                .map_err(|e| anyhow::anyhow!("Invalid --matching: {}", e))
+        })
+        .transpose()?;
+    let date_of = |timestamp: &str| match time_zone {
+        Some(time_zone) => time_zone.date_of(timestamp),
+        None => Some(DateTime::parse_from_rfc3339(timestamp).ok()?.date_naive()),
+    };
+
+    let mut selected: Vec<(&String, &Checkpoint)> = config
+        .checkpoints
+        .iter()
+        .filter(|(name, checkpoint)| {
+            let date = date_of(&checkpoint.timestamp);
+            glob.as_ref()
+                .is_none_or(|glob| glob.is_match(name.as_str()))
+                && select
+                    .prefix
+                    .as_deref()
+                    .is_none_or(|prefix| name.starts_with(prefix))
+                && select.tags.iter().all(|tag| checkpoint.tags.contains(tag))
+                && select
+                    .before
+                    .is_none_or(|before| date.is_some_and(|date| date < before))
+                && select.between.as_deref().is_none_or(|range| {
+                    date.is_some_and(|date| range[0] <= date && date <= range[1])
+                })
+        })
+        .collect();
+    selected.sort_by(|a, b| a.1.timestamp.cmp(&b.1.timestamp));
+
+    if !selected.is_empty() {
+        info!("Selected {} checkpoint(s):", selected.len());
+    }
+    for (name, checkpoint) in &selected {
+        info!(
+            "  {} - {}",
+            name,
+            display_timestamp(&checkpoint.timestamp, time_zone)
+        );
+    }
+    Ok(selected.into_iter().map(|(name, _)| name.clone()).collect())
+}
+
+/// Renders a stored RFC3339 timestamp in the requested zone, or unchanged
+/// when no zone was requested or the timestamp cannot be parsed
+fn display_timestamp(timestamp: &str, time_zone: Option<TimeZoneArg>) -> String {
+    let (Some(time_zone), Ok(parsed)) = (time_zone, DateTime::parse_from_rfc3339(timestamp)) else {
+        return timestamp.to_string();
+    };
+    match time_zone {
+        TimeZoneArg::Local => parsed.with_timezone(&Local).to_rfc3339(),
+        TimeZoneArg::Utc => parsed.with_timezone(&Utc).to_rfc3339(),
+        TimeZoneArg::Named(tz) => parsed.with_timezone(&tz).to_rfc3339(),
+    }
+}
+
+/// Parses a non-zero size like `650MB` for clap
+fn parse_part_size(s: &str) -> Result<u64, String> {
+    match parse_size(s) {
+        Ok(0) => Err("size must be greater than zero".to_string()),
+        Ok(size) => Ok(size),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parses durations like `45s`, `90m`, `12h`, `7d` or `2w`; a bare number is seconds
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+    let multiplier = match suffix.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        other => {
+            return Err(format!(
+                "unknown duration unit '{}'; use s, m, h, d or w",
+                other
+            ));
+        }
+    };
+    Ok(Duration::from_secs(number * multiplier))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum SizeUnit {
+    /// Scale to the largest fitting unit
+    Auto,
+    Bytes,
+    Kb,
+    Mb,
+    Gb,
 }
 
 const ALTS_DIR: &str = ".alts";
 const CONFIG_FILE: &str = "alts.toml";
+/// Gitignore-style exclude file read from the root of the target
+const IGNORE_FILE: &str = ".altsignore";
+/// Appended to files stored as binary deltas against a base checkpoint
+const DELTA_SUFFIX: &str = ".alts-delta";
+/// Entries left out with `checkpoint --skip-errors`, one `<path>: <reason>`
+/// per line, at the root of the checkpoint's tree; never restored
+const ERRORS_FILE: &str = ".alts-errors";
+/// Append-only log of operations on checkpoints, inside `.alts`
+const HISTORY_FILE: &str = "history.log";
+/// Appended to a checkpoint name for its per-file checksum manifest
+const CHECKSUMS_SUFFIX: &str = ".alts-manifest.json";
+/// Content store shared by deduplicated checkpoints, inside `.alts`
+const OBJECTS_DIR: &str = "objects";
+/// Manifest file inside the directory of a deduplicated checkpoint
+const MANIFEST_FILE: &str = "manifest.toml";
+/// Metadata entry and tree directory inside an archive written by `alts export`
+const EXPORT_METADATA_FILE: &str = "checkpoint.toml";
+const EXPORT_TREE_DIR: &str = "tree";
+/// Appended to an archive name for the manifest of `export --split` parts
+const SPLIT_MANIFEST_SUFFIX: &str = ".parts";
+/// Bump together with a new step in `migrate_config_table`
+const CONFIG_VERSION: u32 = 1;
+const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y_%m_%d_%H_%M_%S";
+/// How names were always generated, before `name_template` existed
+const DEFAULT_NAME_TEMPLATE: &str = "{stem}_{timestamp}{ext}";
+const DEFAULT_HASH_PREFIX_LEN: usize = 12;
+/// Characters of a checkpoint message shown by `list`
+const LIST_MESSAGE_WIDTH: usize = 50;
+/// Upper bound on a webhook request, so an unresponsive endpoint cannot
+/// stall `checkpoint`
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Exit code for failures outside the `FailureKind` categories
+const ERROR_EXIT_CODE: u8 = 1;
+/// Shown after `--help`; keep in sync with `FailureKind::exit_code`
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Other failure
+  2  Invalid command line
+  3  Repository not initialized
+  4  Checkpoint or alias not found
+  5  Name or file already exists
+  6  Not enough disk space
+  7  Verification failed
+  8  Repository index missing or unreadable";
+
+/// Failure categories that scripts can tell apart by exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FailureKind {
+    NotInitialized,
+    NotFound,
+    AlreadyExists,
+    NoSpace,
+    VerificationFailed,
+    CorruptIndex,
+}
+
+impl FailureKind {
+    fn exit_code(self) -> u8 {
+        match self {
+            FailureKind::NotInitialized => 3,
+            FailureKind::NotFound => 4,
+            FailureKind::AlreadyExists => 5,
+            FailureKind::NoSpace => 6,
+            FailureKind::VerificationFailed => 7,
+            FailureKind::CorruptIndex => 8,
+        }
+    }
+
+    /// The category of `error`, from a `Failure` anywhere in its chain or
+    /// an I/O error reporting a full disk
+    fn of(error: &anyhow::Error) -> Option<FailureKind> {
+        error.chain().find_map(|cause| {
+            if let Some(failure) = cause.downcast_ref::<Failure>() {
+                Some(failure.kind)
+            } else {
+                cause
+                    .downcast_ref::<std::io::Error>()
+                    .filter(|e| e.kind() == std::io::ErrorKind::StorageFull)
+                    .map(|_| FailureKind::NoSpace)
+            }
+        })
+    }
+}
+
+impl std::fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            FailureKind::NotInitialized => "repository not initialized",
+            FailureKind::NotFound => "not found",
+            FailureKind::AlreadyExists => "already exists",
+            FailureKind::NoSpace => "not enough disk space",
+            FailureKind::VerificationFailed => "verification failed",
+            FailureKind::CorruptIndex => "corrupt index",
+        })
+    }
+}
+
+/// An error message tagged with its `FailureKind`
+#[derive(Debug)]
+struct Failure {
+    kind: FailureKind,
+    message: String,
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+fn failure(kind: FailureKind, message: String) -> anyhow::Error {
+    anyhow::Error::new(Failure { kind, message })
+}
 
 #[derive(Serialize, Deserialize)]
 struct Checkpoint {
+    /// RFC 3339 creation time; empty when lost, see `alts touch`
+    #[serde(default)]
     timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Tree content hash, recorded for content-named checkpoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    /// Checkpoint that this one's `.alts-delta` files are patches against
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delta_base: Option<String>,
+    #[serde(default, skip_serializing_if = "Storage::is_dir")]
+    storage: Storage,
+    /// Version of alts that created the checkpoint; unknown for older ones
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_by_version: Option<String>,
+    /// Bytes the stored data took when written, so reports need not walk it;
+    /// measured on demand for older checkpoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stored_size: Option<u64>,
+    /// Earlier checkpoint whose unchanged files this one hard links to
+    /// instead of holding copies; either can be deleted independently
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    linked_from: Option<String>,
+    /// Some entries could not be read and are missing (`--skip-errors`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+    /// Directory the checkpoint was taken from with `checkpoint --from`, as
+    /// recorded for a target; restores go back there
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_dir: Option<String>,
+}
+
+impl Checkpoint {
+    /// The directory this checkpoint was taken from and restores into
+    fn source<'a>(&'a self, config: &'a Config) -> &'a str {
+        self.source_dir.as_deref().unwrap_or(&config.target_dir)
+    }
+
+    /// Where the checkpoint's data lives inside `.alts`
+    fn storage_path(&self, alts_dir: &Path, name: &str) -> PathBuf {
+        match self.storage {
+            Storage::Dir => alts_dir.join(Path::new(name)),
+            Storage::TarGz => alts_dir.join(format!("{}.tar.gz", name)),
+            Storage::Objects => alts_dir.join(Path::new(name)),
+        }
+    }
+}
+
+/// How a checkpoint is kept on disk
+#[derive(Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+enum Storage {
+    /// A plain copy of the tree
+    #[default]
+    #[serde(rename = "dir")]
+    Dir,
+    /// A gzip-compressed tarball, `<name>.tar.gz`
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    /// A directory holding only a manifest of objects in `.alts/objects`
+    #[serde(rename = "objects")]
+    Objects,
+}
+
+/// Paths of a deduplicated checkpoint mapped to what they hold
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    /// Metadata of the checkpoint's top-level directory
+    #[serde(default)]
+    root: FileMeta,
+    entries: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ManifestEntry {
+    Dir {
+        #[serde(flatten)]
+        meta: FileMeta,
+    },
+    File {
+        hash: String,
+        #[serde(flatten)]
+        meta: FileMeta,
+    },
+    Symlink {
+        target: PathBuf,
+    },
+}
+
+/// Permission bits and modification time of a file or directory, carried
+/// over to its copy. Manifests written before they were recorded leave
+/// both unset.
+#[derive(Serialize, Deserialize, Default, Clone, Copy)]
+struct FileMeta {
+    /// Unix permission bits; not recorded on other platforms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+    /// Nanoseconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mtime_ns: Option<i64>,
+}
+
+impl FileMeta {
+    fn of(metadata: &fs::Metadata) -> Self {
+        let mtime = filetime::FileTime::from_last_modification_time(metadata);
+        FileMeta {
+            mode: unix_mode(metadata),
+            mtime_ns: mtime
+                .unix_seconds()
+                .checked_mul(1_000_000_000)
+                .and_then(|ns| ns.checked_add(mtime.nanoseconds().into())),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)
+            .with_context(|| format!("Failed to read metadata of '{}'", path.display()))?;
+        Ok(Self::of(&metadata))
+    }
+
+    /// Sets the mtime before the mode, since a read-only mode could forbid it
+    fn apply(&self, path: &Path) -> Result<()> {
+        if let Some(ns) = self.mtime_ns {
+            let mtime = filetime::FileTime::from_unix_time(
+                ns.div_euclid(1_000_000_000),
+                ns.rem_euclid(1_000_000_000) as u32,
+            );
+            filetime::set_file_mtime(path, mtime)
+                .with_context(|| format!("Failed to set mtime of '{}'", path.display()))?;
+        }
+        if let Some(mode) = self.mode {
+            set_unix_mode(path, mode)
+                .with_context(|| format!("Failed to set permissions of '{}'", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn unix_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn unix_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+fn set_unix_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn set_unix_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+impl Storage {
+    fn is_dir(&self) -> bool {
+        *self == Storage::Dir
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct Config {
+    /// Schema version; repositories created before versioning read as 0
+    #[serde(default)]
+    version: u32,
     target_dir: String,
+    /// strftime pattern for generated checkpoint names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp_format: Option<String>,
+    /// Pattern for generated checkpoint names, see `render_name_template`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name_template: Option<String>,
+    /// `info` warns when free space on the `.alts` volume drops below this (e.g. "10GB")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_free_space: Option<String>,
+    /// Hash characters used to name `--content-name` checkpoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash_prefix_len: Option<usize>,
+    /// Machine-local exclude patterns applied to every checkpoint
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exclude_always: Vec<String>,
+    /// Files larger than this (e.g. "500MB") are left out of checkpoints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_file_size: Option<String>,
+    /// Bulk deletions of more checkpoints than this ask for confirmation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confirm_if_deleting_more_than: Option<usize>,
+    /// Bulk deletions freeing more than this (e.g. "5GB") ask for confirmation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    confirm_if_reclaiming_more_than: Option<String>,
     #[serde(default)]
     checkpoints: BTreeMap<String, Checkpoint>,
+    /// Movable labels mapping to checkpoint names
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    aliases: BTreeMap<String, String>,
+    /// Endpoint notified after every checkpoint
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    webhook: Option<Webhook>,
+    /// Storage format for new checkpoints; "tar.gz" compresses them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    compression: Option<Storage>,
+    /// Store file contents once in `.alts/objects` and give each checkpoint a
+    /// manifest instead of a copy
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    dedup: bool,
+    /// After each checkpoint, delete the oldest ones beyond this count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_checkpoints: Option<usize>,
+    /// After each checkpoint, delete those older than this many days
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_age_days: Option<u64>,
+    /// Hard link files whose size, mtime and mode are unchanged since the
+    /// newest plain checkpoint instead of copying them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    incremental: bool,
 }
 
-fn init(dir_name: &str) -> Result<()> {
+#[derive(Serialize, Deserialize)]
+struct Webhook {
+    url: String,
+    /// Sent verbatim as the `Authorization` header, e.g. "Bearer <token>"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    authorization: Option<String>,
+}
+
+/// JSON body POSTed to the webhook when a checkpoint is created
+#[derive(Serialize)]
+struct CheckpointEvent<'a> {
+    event: &'static str,
+    name: &'a str,
+    timestamp: &'a str,
+    /// Bytes of file content copied
+    size: u64,
+    host: String,
+}
+
+/// The closest directory above `dir` holding an initialized `.alts`, like
+/// git looking for an enclosing repository
+fn enclosing_repository(dir: &Path) -> Result<Option<PathBuf>> {
+    let dir = dir.canonicalize()?;
+    Ok(dir
+        .ancestors()
+        .skip(1)
+        .find(|parent| parent.join(ALTS_DIR).join(CONFIG_FILE).is_file())
+        .map(Path::to_path_buf))
+}
+
+/// Checks that `dir_name` is an existing directory under `current_dir` (or
+/// anywhere with `allow_outside`) and not inside its `.alts`, returning the
+/// path to record for it
+fn recorded_dir(current_dir: &Path, dir_name: &str, allow_outside: bool) -> Result<String> {
     // Normalize the path and check if it exists under current directory
-    let current_dir = std::env::current_dir()?;
     let current_dir_normalized = current_dir.canonicalize()?;
     let target_path = current_dir.join(dir_name);
 
@@ -80,35 +1147,108 @@ fn init(dir_name: &str) -> Result<()> {
     let target_path_normalized = target_path.canonicalize()?;
 
     // Check if the target directory is under the current directory
-    match target_path_normalized.strip_prefix(&current_dir_normalized) {
-        Ok(relative_path) => {
-            // Ensure the path doesn't contain ".." (parent directory references)
-            if relative_path
-                .components()
-                .any(|c| c == std::path::Component::ParentDir)
-            {
+    if !allow_outside {
+        match target_path_normalized.strip_prefix(&current_dir_normalized) {
+            Ok(relative_path) => {
+                // Ensure the path doesn't contain ".." (parent directory references)
+                if relative_path
+                    .components()
+                    .any(|c| c == std::path::Component::ParentDir)
+                {
+                    return Err(anyhow::anyhow!(
+                        "Directory '{}' is not under current directory",
+                        dir_name
+                    ));
+                }
+            }
+            Err(_) => {
                 return Err(anyhow::anyhow!(
                     "Directory '{}' is not under current directory",
                     dir_name
                 ));
             }
         }
-        Err(_) => {
+    }
+
+    let alts_dir_normalized = current_dir_normalized.join(ALTS_DIR);
+    if target_path_normalized.starts_with(&alts_dir_normalized) {
+        return Err(anyhow::anyhow!(
+            "'{}' is inside the repository's own '{}' directory",
+            dir_name,
+            ALTS_DIR
+        ));
+    }
+    if alts_dir_normalized.starts_with(&target_path_normalized) {
+        warn!(
+            "'{}' contains the repository's '{}'; checkpoints leave it out and restores keep it",
+            dir_name, ALTS_DIR
+        );
+    }
+
+    let recorded = if allow_outside {
+        // An absolute path keeps working from the repository root, wherever it lives
+        target_path_normalized.to_str()
+    } else {
+        // Use the canonicalized relative path without trailing slashes; the
+        // current directory itself is recorded as `.`
+        match target_path_normalized.strip_prefix(&current_dir_normalized) {
+            Ok(relative) if relative.as_os_str().is_empty() => Some("."),
+            Ok(relative) => relative.to_str(),
+            Err(_) => None,
+        }
+    }
+    .ok_or_else(|| anyhow::anyhow!("Invalid directory name"))?
+    .to_string();
+    Ok(recorded)
+}
+
+fn init(
+    dir_name: &str,
+    timestamp_format: Option<String>,
+    allow_outside: bool,
+    nested: bool,
+) -> Result<()> {
+    if let Some(format) = &timestamp_format {
+        validate_timestamp_format(format)?;
+    }
+
+    let current_dir = repo_dir()?;
+    if let Some(enclosing) = enclosing_repository(&current_dir)? {
+        if !nested {
             return Err(anyhow::anyhow!(
-                "Directory '{}' is not under current directory",
-                dir_name
+                "'{}' is inside the alts repository at '{}'; pass --nested to create one here anyway",
+                current_dir.display(),
+                enclosing.display()
             ));
         }
+        warn!(
+            "Creating a repository nested inside the one at '{}'",
+            enclosing.display()
+        );
     }
+    let target_dir = recorded_dir(&current_dir, dir_name, allow_outside)?;
+    let alts_dir = current_dir.join(ALTS_DIR);
 
     // Check if repository is already initialized
-    let alts_dir = current_dir.join(ALTS_DIR);
-    if alts_dir.exists() {
+    if alts_dir.join(CONFIG_FILE).exists() {
         return Err(anyhow::anyhow!(
             "Repository is already initialized. Please manually remove the '{}' directory first.",
             ALTS_DIR
         ));
     }
+    if alts_dir.exists() {
+        // A previous init was interrupted or the config was lost
+        warn!(
+            "'{}' exists but '{}' is missing; the repository is incomplete",
+            ALTS_DIR, CONFIG_FILE
+        );
+        if !confirm("Complete initialization using the existing directory?")? {
+            return Err(anyhow::anyhow!(
+                "Initialization aborted. Remove '{}' manually or re-run interactively to complete it.",
+                ALTS_DIR
+            ));
+        }
+    }
 
     // Create .alts directory
     fs::create_dir_all(&alts_dir).context("Failed to create .alts directory")?;
@@ -116,16 +1256,26 @@ fn init(dir_name: &str) -> Result<()> {
     // Write config file using toml serialization
     let config_path = alts_dir.join(CONFIG_FILE);
     let config = Config {
-        // Use the canonicalized relative path without trailing slashes
-        target_dir: target_path_normalized
-            .file_name()
-            .and_then(|n| n.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Invalid directory name"))?
-            .to_string(),
+        version: CONFIG_VERSION,
+        target_dir,
+        timestamp_format,
+        name_template: None,
+        min_free_space: None,
+        hash_prefix_len: None,
+        exclude_always: Vec::new(),
+        max_file_size: None,
+        confirm_if_deleting_more_than: None,
+        confirm_if_reclaiming_more_than: None,
         checkpoints: BTreeMap::new(),
+        aliases: BTreeMap::new(),
+        webhook: None,
+        compression: None,
+        dedup: false,
+        max_checkpoints: None,
+        max_age_days: None,
+        incremental: false,
     };
-    let config_content = toml::to_string_pretty(&config).context("Failed to serialize config")?;
-    fs::write(&config_path, config_content).context("Failed to write config file")?;
+    write_config(&config_path, &config)?;
 
     info!(
         "Initialized alts repository tracking '{}'",
@@ -134,366 +1284,6549 @@ fn init(dir_name: &str) -> Result<()> {
     Ok(())
 }
 
-fn load_config() -> Result<Config> {
-    let current_dir = std::env::current_dir()?;
-    let config_path = current_dir.join(ALTS_DIR).join(CONFIG_FILE);
-
-    if !config_path.exists() {
-        return Err(anyhow::anyhow!(
-            "Not initialized. Run 'alts init <dir_name>' first"
-        ));
+/// Checkpoints a freshly initialized repository, unless its target is empty
+fn first_checkpoint(name: Option<String>) -> Result<()> {
+    let config = load_config()?;
+    if is_empty_dir(&repo_dir()?.join(&config.target_dir))? {
+        warn!(
+            "Target directory '{}' is empty; skipping the first checkpoint",
+            config.target_dir
+        );
+        return Ok(());
     }
-
-    let content = fs::read_to_string(&config_path)?;
-    let config: Config = toml::from_str(&content).context("Failed to parse config file")?;
-
-    Ok(config)
+    checkpoint(&CheckpointArgs {
+        name,
+        ..Default::default()
+    })?;
+    Ok(())
 }
 
-fn save_config(config: &Config) -> Result<()> {
-    let current_dir = std::env::current_dir()?;
-    let config_path = current_dir.join(ALTS_DIR).join(CONFIG_FILE);
-    let config_content = toml::to_string_pretty(&config).context("Failed to serialize config")?;
-    fs::write(&config_path, config_content).context("Failed to write config file")?;
-    Ok(())
+fn is_empty_dir(path: &Path) -> Result<bool> {
+    Ok(fs::read_dir(path)
+        .context("Failed to read target directory")?
+        .next()
+        .is_none())
 }
 
-fn checkpoint(name: Option<String>) -> Result<()> {
-    // Load config
-    let mut config = load_config()?;
-    let target_dir = config.target_dir.clone();
+/// Asks a yes/no question on the terminal; answers "no" when stdin is not interactive
+fn confirm(prompt: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
 
-    let current_dir = std::env::current_dir()?;
-    let target_path = current_dir.join(&target_dir);
+    eprint!("{} [y/N] ", prompt);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
 
-    // Check if target exists and is not empty
-    if !target_path.exists() {
+/// Lets the user choose a checkpoint from a numbered list, newest first, by
+/// number or name. Fails without a terminal, or when nothing is chosen.
+fn pick_checkpoint(config: &Config, action: &str) -> Result<String> {
+    if !std::io::stdin().is_terminal() {
         return Err(anyhow::anyhow!(
-            "Target directory '{}' does not exist",
-            target_dir
+            "No checkpoint given; name one, or run interactively to choose from a list"
         ));
     }
+    if config.checkpoints.is_empty() {
+        return Err(anyhow::anyhow!("No checkpoints to {}", action));
+    }
 
-    let is_empty = fs::read_dir(&target_path)
-        .context("Failed to read target directory")?
-        .next()
-        .is_none();
+    let mut checkpoints: Vec<_> = config.checkpoints.iter().collect();
+    checkpoints.sort_by(|a, b| b.1.timestamp.cmp(&a.1.timestamp));
+    for (i, (name, checkpoint)) in checkpoints.iter().enumerate() {
+        let message = checkpoint
+            .message
+            .as_deref()
+            .map(|m| format!(" - {}", truncate_message(m, LIST_MESSAGE_WIDTH)))
+            .unwrap_or_default();
+        eprintln!(
+            "{:>4}) {} - {}{}",
+            i + 1,
+            name,
+            checkpoint.timestamp,
+            message
+        );
+    }
+
+    loop {
+        eprint!(
+            "Checkpoint to {} [1-{}, empty to cancel]: ",
+            action,
+            checkpoints.len()
+        );
+        std::io::stderr().flush()?;
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        let answer = answer.trim();
+        if answer.is_empty() {
+            return Err(anyhow::anyhow!("No checkpoint chosen"));
+        }
+        let chosen = match answer.parse::<usize>() {
+            Ok(n) => n.checked_sub(1).and_then(|i| checkpoints.get(i)),
+            Err(_) => checkpoints.iter().find(|(name, _)| name.as_str() == answer),
+        };
+        match chosen {
+            Some((name, _)) => return Ok(name.to_string()),
+            None => eprintln!("No such checkpoint: {}", answer),
+        }
+    }
+}
+
+/// Rejects strftime patterns chrono cannot render or whose output is not a
+/// single path component
+fn validate_timestamp_format(format: &str) -> Result<()> {
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(anyhow::anyhow!("Invalid timestamp format '{}'", format));
+    }
 
-    if is_empty {
+    let sample = Utc::now().format(format).to_string();
+    if sample.is_empty() || sample.contains(['/', '\\']) {
         return Err(anyhow::anyhow!(
-            "Target directory '{}' is empty",
-            target_dir
+            "Timestamp format '{}' must produce a non-empty name without path separators (got '{}')",
+            format,
+            sample
         ));
     }
 
-    let alts_dir = current_dir.join(ALTS_DIR);
-    let checkpoint_name = match name {
-        Some(n) => {
-            // Normalize the checkpoint name
-            let normalized_name = Path::new(&n);
-            let checkpoint_name = normalized_name
-                .file_name()
-                .and_then(|f| f.to_str())
-                .unwrap_or(&n)
-                .to_string();
+    Ok(())
+}
 
-            checkpoint_name
-        }
-        None => {
-            // Generate name with timestamp
-            let now: DateTime<Utc> = Utc::now();
-            let timestamp = now.format("%Y_%m_%d_%H_%M_%S").to_string();
+/// Repository directory given with `--repo`, set once before any command runs
+static REPO_DIR: OnceLock<PathBuf> = OnceLock::new();
 
-            // Handle file extensions correctly - insert timestamp before extension
-            let target_path = Path::new(&target_dir);
-            let file_stem = target_path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or(&target_dir);
-            let extension = target_path
-                .extension()
-                .and_then(|s| s.to_str())
-                .map(|ext| format!(".{}", ext))
-                .unwrap_or_default();
+/// Directory holding `.alts`: the `--repo` path if given, else the current one
+fn repo_dir() -> Result<PathBuf> {
+    match REPO_DIR.get() {
+        Some(dir) => Ok(dir.clone()),
+        None => Ok(std::env::current_dir()?),
+    }
+}
 
-            format!("{}_{}{}", file_stem, timestamp, extension)
-        }
-    };
+fn load_config() -> Result<Config> {
+    load_config_at(&repo_dir()?)
+}
 
-    // Check if checkpoint name already exists in index
-    if config.checkpoints.contains_key(&checkpoint_name) {
-        return Err(anyhow::anyhow!(
-            "Checkpoint name '{}' already exists",
-            checkpoint_name
+/// Loads the config of the repository rooted at `root`
+fn load_config_at(root: &Path) -> Result<Config> {
+    let config_path = root.join(ALTS_DIR).join(CONFIG_FILE);
+
+    if !config_path.exists() {
+        if root.join(ALTS_DIR).is_dir() {
+            return Err(failure(
+                FailureKind::CorruptIndex,
+                format!(
+                    "Corrupt repository: {} exists but {} is missing. Run 'alts init <dir_name>' to complete initialization",
+                    ALTS_DIR, CONFIG_FILE
+                ),
+            ));
+        }
+        return Err(failure(
+            FailureKind::NotInitialized,
+            "Not initialized. Run 'alts init <dir_name>' first".to_string(),
         ));
     }
 
-    let checkpoint_path = alts_dir.join(&checkpoint_name);
-
-    info!("Creating checkpoint '{}'...", checkpoint_name);
-    copy_dir_recursive(&target_path, &checkpoint_path)?;
+    let content = fs::read_to_string(&config_path)?;
+    let config: Config = toml::from_str(&content).map_err(|e| {
+        failure(
+            FailureKind::CorruptIndex,
+            format!("Failed to parse config file: {}", e),
+        )
+    })?;
+
+    if config.version > CONFIG_VERSION {
+        return Err(anyhow::anyhow!(
+            "Config version {} is newer than this alts supports ({}); please upgrade alts",
+            config.version,
+            CONFIG_VERSION
+        ));
+    }
+    if config.version < CONFIG_VERSION {
+        warn!(
+            "Config version {} is outdated (current is {}); run 'alts migrate'",
+            config.version, CONFIG_VERSION
+        );
+    }
+
+    Ok(config)
+}
+
+/// Runs `cmd` and checkpoints depending on its outcome. Returns the command's
+/// exit code so the caller can pass it on.
+fn run(
+    cmd: &[String],
+    on_failure: bool,
+    name: Option<String>,
+    message: Option<String>,
+) -> Result<i32> {
+    // Fail before running anything if the repository is unusable
+    load_config()?;
+
+    let command_line = cmd.join(" ");
+    info!("Running: {}", command_line);
+    let status = std::process::Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .status()
+        .with_context(|| format!("Failed to run '{}'", cmd[0]))?;
+    let code = status.code().unwrap_or(1);
+
+    if status.success() == on_failure {
+        info!("Command exited with {}, not creating a checkpoint", status);
+        return Ok(code);
+    }
+
+    let args = CheckpointArgs {
+        name,
+        message: Some(message.unwrap_or(command_line)),
+        ..Default::default()
+    };
+    checkpoint(&args)?;
+    Ok(code)
+}
+
+/// Whether `file_name` in `.alts` is scratch space of an alts command that is
+/// running or was interrupted: staging and materialize directories, a
+/// checkpoint set aside by `checkpoint --force`, a config being written
+fn is_work_entry(file_name: &str) -> bool {
+    file_name.starts_with('.') || file_name.starts_with(&format!("{}.tmp-", CONFIG_FILE))
+}
+
+/// Entries in `.alts` for which `is_work_entry` holds, sorted
+fn work_entries(alts_dir: &Path) -> Result<Vec<String>> {
+    let mut found = Vec::new();
+    for entry in fs::read_dir(alts_dir).context("Failed to read .alts directory")? {
+        let file_name = entry?.file_name().to_string_lossy().into_owned();
+        if is_work_entry(&file_name) {
+            found.push(file_name);
+        }
+    }
+    found.sort();
+    Ok(found)
+}
+
+/// Names of the entries in `.alts` that belong to no indexed checkpoint and
+/// are neither the repository's own files nor `is_work_entry`, sorted
+fn orphaned_entries(config: &Config, alts_dir: &Path) -> Result<Vec<String>> {
+    let stored: BTreeSet<PathBuf> = config
+        .checkpoints
+        .iter()
+        .flat_map(|(name, c)| {
+            [
+                c.storage_path(alts_dir, name),
+                checksums_path(alts_dir, name),
+            ]
+        })
+        .collect();
+    let mut orphans = Vec::new();
+    for entry in fs::read_dir(alts_dir).context("Failed to read .alts directory")? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let is_config = file_name == CONFIG_FILE || file_name == format!("{}.bak", CONFIG_FILE);
+        let is_store = file_name == OBJECTS_DIR || file_name == HISTORY_FILE;
+        if !is_config && !is_store && !is_work_entry(&file_name) && !stored.contains(&entry.path())
+        {
+            orphans.push(file_name);
+        }
+    }
+    orphans.sort();
+    Ok(orphans)
+}
+
+/// Cross-checks the index and `.alts` and reports each class of problem
+/// separately. Fails if anything is found so it can gate automation.
+fn fsck() -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+    let mut issues = 0;
+
+    let missing: Vec<&String> = config
+        .checkpoints
+        .iter()
+        .filter(|(name, c)| fs::symlink_metadata(c.storage_path(&alts_dir, name)).is_err())
+        .map(|(name, _)| name)
+        .collect();
+    if !missing.is_empty() {
+        warn!(
+            "{} indexed checkpoint(s) have no directory (fix: alts prune):",
+            missing.len()
+        );
+        for name in &missing {
+            warn!("  {}", name);
+        }
+        issues += missing.len();
+    }
+
+    let orphans = orphaned_entries(&config, &alts_dir)?;
+    if !orphans.is_empty() {
+        warn!(
+            "{} item(s) in .alts are not in the index (fix: alts prune --orphans):",
+            orphans.len()
+        );
+        for name in &orphans {
+            warn!("  {}", name);
+        }
+        issues += orphans.len();
+    }
+
+    let work = work_entries(&alts_dir)?;
+    if !work.is_empty() {
+        warn!(
+            "{} item(s) in .alts were left by a running or interrupted alts command; \
+             a .replace-* item holds a checkpoint being replaced with --force and may be \
+             its only copy (fix: move back or delete by hand once no alts command runs):",
+            work.len()
+        );
+        for name in &work {
+            warn!("  {}", name);
+        }
+        issues += work.len();
+    }
+
+    let mut mismatched = Vec::new();
+    for (name, checkpoint) in &config.checkpoints {
+        let (Some(expected), false) = (&checkpoint.hash, missing.contains(&name)) else {
+            continue;
+        };
+        let actual = with_materialized(&config, &alts_dir, name, tree_hash)
+            .with_context(|| format!("Failed to hash checkpoint '{}'", name))?;
+        if &actual != expected {
+            mismatched.push(name);
+        }
+    }
+    if !mismatched.is_empty() {
+        warn!(
+            "{} checkpoint(s) no longer match their recorded hash (fix: delete and re-create them):",
+            mismatched.len()
+        );
+        for name in &mismatched {
+            warn!("  {}", name);
+        }
+        issues += mismatched.len();
+    }
+
+    let dangling: Vec<(&String, &String)> = config
+        .aliases
+        .iter()
+        .filter(|(_, target)| !config.checkpoints.contains_key(*target))
+        .collect();
+    if !dangling.is_empty() {
+        warn!(
+            "{} alias(es) point to unknown checkpoints (fix: alts alias rm <label>):",
+            dangling.len()
+        );
+        for (label, target) in &dangling {
+            warn!("  {} -> {}", label, target);
+        }
+        issues += dangling.len();
+    }
+
+    if issues > 0 {
+        return Err(anyhow::anyhow!("fsck found {} issue(s)", issues));
+    }
+    info!(
+        "No issues found in {} checkpoint(s)",
+        config.checkpoints.len()
+    );
+    Ok(())
+}
+
+/// Reports checkpoints present in only one of the two repositories and shared
+/// names whose content differs. Fails if the repositories are not equivalent.
+fn compare_repos(other: &str) -> Result<()> {
+    let config = load_config()?;
+    let alts_dir = repo_dir()?.join(ALTS_DIR);
+    let other_root = Path::new(other);
+    let other_config = load_config_at(other_root)
+        .with_context(|| format!("Failed to load repository at '{}'", other))?;
+    let other_alts_dir = other_root.join(ALTS_DIR);
+    let mut differences = 0;
+
+    for (label, only) in [
+        ("this repository", missing_from(&config, &other_config)),
+        (other, missing_from(&other_config, &config)),
+    ] {
+        if !only.is_empty() {
+            warn!("{} checkpoint(s) only in {}:", only.len(), label);
+            for name in &only {
+                warn!("  {}", name);
+            }
+            differences += only.len();
+        }
+    }
+
+    let mut compared = 0;
+    for (name, checkpoint) in &config.checkpoints {
+        let Some(other_checkpoint) = other_config.checkpoints.get(name) else {
+            continue;
+        };
+        let ours = stored_or_computed_hash(&config, &alts_dir, name, checkpoint)?;
+        let theirs =
+            stored_or_computed_hash(&other_config, &other_alts_dir, name, other_checkpoint)?;
+        compared += 1;
+        match (ours, theirs) {
+            (Some(ours), Some(theirs)) if ours == theirs => debug!("Match: {}", name),
+            (Some(_), Some(_)) => {
+                warn!("Content differs: {}", name);
+                differences += 1;
+            }
+            _ => {
+                warn!("Directory missing on one side: {}", name);
+                differences += 1;
+            }
+        }
+    }
+
+    if differences > 0 {
+        return Err(anyhow::anyhow!(
+            "Repositories differ: {} difference(s) found",
+            differences
+        ));
+    }
+    info!(
+        "Repositories are equivalent: {} checkpoint(s) compared",
+        compared
+    );
+    Ok(())
+}
+
+/// Names indexed in `config` but not in `other`
+fn missing_from<'a>(config: &'a Config, other: &Config) -> Vec<&'a String> {
+    config
+        .checkpoints
+        .keys()
+        .filter(|name| !other.checkpoints.contains_key(*name))
+        .collect()
+}
+
+/// The recorded tree hash of a checkpoint, or one computed from its content;
+/// `None` when its data is missing
+fn stored_or_computed_hash(
+    config: &Config,
+    alts_dir: &Path,
+    name: &str,
+    checkpoint: &Checkpoint,
+) -> Result<Option<String>> {
+    let checkpoint_path = checkpoint.storage_path(alts_dir, name);
+    if !checkpoint_path.exists() {
+        return Ok(None);
+    }
+    if let Some(hash) = &checkpoint.hash {
+        return Ok(Some(hash.clone()));
+    }
+    with_materialized(config, alts_dir, name, tree_hash)
+        .map(Some)
+        .with_context(|| format!("Failed to hash '{}'", checkpoint_path.display()))
+}
+
+fn migrate() -> Result<()> {
+    let current_dir = repo_dir()?;
+    let config_path = current_dir.join(ALTS_DIR).join(CONFIG_FILE);
+    if !config_path.exists() {
+        // Reuse the detailed not-initialized / corrupt messages
+        load_config()?;
+    }
+
+    let content = fs::read_to_string(&config_path)?;
+    let mut table: toml::Table = toml::from_str(&content).context("Failed to parse config file")?;
+    let from = match table.get("version") {
+        None => 0,
+        Some(value) => value
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow::anyhow!("Config 'version' is not a valid number"))?,
+    };
+
+    if from > CONFIG_VERSION {
+        return Err(anyhow::anyhow!(
+            "Config version {} is newer than this alts supports ({})",
+            from,
+            CONFIG_VERSION
+        ));
+    }
+    if from == CONFIG_VERSION {
+        info!("Config is already at version {}, nothing to do", from);
+        return Ok(());
+    }
+
+    let changes = migrate_config_table(&mut table, from);
+
+    // Make sure the result is a config we can actually load before replacing anything
+    let migrated = toml::to_string_pretty(&table).context("Failed to serialize config")?;
+    let config: Config =
+        toml::from_str(&migrated).context("Migrated config does not match the current schema")?;
+
+    let backup_path = config_path.with_extension("toml.bak");
+    fs::copy(&config_path, &backup_path).context("Failed to back up config file")?;
+    info!("Backed up old config to '{}'", backup_path.display());
+
+    save_config(&config)?;
+    for change in &changes {
+        info!("  {}", change);
+    }
+    info!(
+        "Migrated config from version {} to {}",
+        from, CONFIG_VERSION
+    );
+    Ok(())
+}
+
+/// Applies each schema upgrade after `from` in order, returning a description
+/// of every change made
+fn migrate_config_table(table: &mut toml::Table, from: u32) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    if from < 1 {
+        table.insert("version".to_string(), toml::Value::Integer(1));
+        changes.push("v0 -> v1: added schema version field".to_string());
+    }
+
+    changes
+}
+
+fn save_config(config: &Config) -> Result<()> {
+    let current_dir = repo_dir()?;
+    let config_path = current_dir.join(ALTS_DIR).join(CONFIG_FILE);
+    write_config(&config_path, config)
+}
+
+/// Replaces the config at `config_path` atomically: the new content goes to
+/// a synced temporary file that is then renamed over it, so a crash or a
+/// full disk leaves either the old index or the new one, never a torn file
+fn write_config(config_path: &Path, config: &Config) -> Result<()> {
+    let config_content = toml::to_string_pretty(config).context("Failed to serialize config")?;
+    let temp_path = config_path.with_extension(format!("toml.tmp-{}", std::process::id()));
+    let written = fs::File::create(&temp_path).and_then(|mut file| {
+        file.write_all(config_content.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| fs::rename(&temp_path, config_path)) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(anyhow::Error::new(e).context("Failed to write config file"));
+    }
+    // Persist the rename itself; directories cannot be opened for this everywhere
+    if let Some(dir) = config_path.parent()
+        && let Ok(dir) = fs::File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+fn checkpoint(args: &CheckpointArgs) -> Result<String> {
+    // Load config
+    let mut config = load_config()?;
+    let message = args.message.as_deref().map(read_message).transpose()?;
+    let timestamp_format = args
+        .timestamp_format
+        .clone()
+        .or_else(|| config.timestamp_format.clone())
+        .unwrap_or_else(|| DEFAULT_TIMESTAMP_FORMAT.to_string());
+
+    let current_dir = repo_dir()?;
+    let source_dir = match &args.from {
+        Some(dir) => Some(recorded_dir(&current_dir, dir, false)?)
+            .filter(|recorded| *recorded != config.target_dir),
+        None => None,
+    };
+    let target_dir = source_dir
+        .clone()
+        .unwrap_or_else(|| config.target_dir.clone());
+    let target_path = current_dir.join(&target_dir);
+    // A target of `.` names generated checkpoints after the directory it is
+    let name_base = match Path::new(&target_dir).file_name() {
+        Some(_) => target_dir.clone(),
+        None => current_dir
+            .canonicalize()?
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "checkpoint".to_string()),
+    };
+
+    // Check if target exists and is not empty; an archive stands in for it
+    if args.from_archive.is_none() {
+        if !target_path.exists() {
+            return Err(anyhow::anyhow!(
+                "Target directory '{}' does not exist; 'alts restore <name>' recreates it from a checkpoint",
+                target_dir
+            ));
+        }
+
+        if is_empty_dir(&target_path)? {
+            return Err(anyhow::anyhow!(
+                "Target directory '{}' is empty",
+                target_dir
+            ));
+        }
+    }
+
+    let only_paths = if args.git_tracked_only {
+        Some(git_tracked_paths(&target_path)?)
+    } else {
+        None
+    };
+    let defaults = target_copy_options(&config, &target_path)?;
+    // The target's own ignore file sits between the config and the command line
+    let exclude_from = defaults
+        .exclude_from
+        .iter()
+        .chain(&args.exclude_from)
+        .cloned()
+        .collect();
+    let mut options = CopyOptions {
+        snapshot_symlink_targets: args.snapshot_symlink_targets,
+        dereference: args.dereference,
+        exclude_from,
+        only_paths,
+        preserve_hardlinks: args.preserve_hardlinks,
+        exclude_dotalts: !args.include_dotalts,
+        verify: args.verify,
+        skip_errors: args.skip_errors,
+        dry_run: args.dry_run,
+        progress_interval: args
+            .progress_lines
+            .then(|| Duration::from_millis(args.progress_interval)),
+        ..defaults
+    };
+    if args.include_dotalts {
+        warn!(
+            "--include-dotalts: any '{}' inside the target is copied, except this repository's own",
+            ALTS_DIR
+        );
+    }
+    if args.preserve_hardlinks && !cfg!(unix) {
+        warn!("--preserve-hardlinks is only supported on Unix; copying every path separately");
+    }
+
+    let storage = configured_storage(&config)?;
+
+    let alts_dir = current_dir.join(ALTS_DIR);
+    // Moved out of the way first so nothing below mistakes it for a base
+    let replaced = match &args.name {
+        Some(name) if args.force && !args.dry_run => {
+            set_aside_checkpoint(&mut config, &alts_dir, &validate_checkpoint_name(name)?)?
+        }
+        _ => None,
+    };
+    let delta_base = match &args.delta_against {
+        Some(base) => {
+            let base = resolve_checkpoint_name(&config, base);
+            find_checkpoint(&config, &alts_dir, base)?;
+            Some(base.to_string())
+        }
+        None => None,
+    };
+    // Only plain copies hold every unchanged file under its own path
+    let link_base = if config.incremental
+        && !args.full
+        && storage == Storage::Dir
+        && delta_base.is_none()
+        && !args.content_name
+        && args.from_archive.is_none()
+    {
+        newest_plain_checkpoint(&config, &alts_dir, &target_dir)
+    } else {
+        None
+    };
+    options.link_unchanged_from = link_base.as_ref().map(|base| alts_dir.join(base));
+    // One planning walk serves the free space check and the progress total
+    if !args.dry_run && args.from_archive.is_none() {
+        let planned = plan_copy(&target_path, &options)?;
+        ensure_free_space(&alts_dir, &planned)?;
+        options.planned_files = Some(planned.files);
+    }
+    let created = creation_time(args.no_timestamps)?;
+    let started = Instant::now();
+    let (checkpoint_name, copied, hash) = if args.content_name {
+        match content_named_checkpoint(&config, &alts_dir, &target_path, &options)? {
+            (name, Some((copied, hash))) => (name, copied, Some(hash)),
+            // Identical content is already stored; nothing to do
+            (existing, None) => return Ok(existing),
+        }
+    } else {
+        let checkpoint_name = match &args.name {
+            Some(n) => validate_checkpoint_name(n)?,
+            // Sequential names keep deterministic mode independent of the clock
+            None if args.no_timestamps => (1..)
+                .map(|n| generated_name(&name_base, &n.to_string()))
+                .find(|name| {
+                    !config.checkpoints.contains_key(name) && !alts_dir.join(name).exists()
+                })
+                .expect("sequence numbers are unbounded"),
+            None => {
+                validate_timestamp_format(&timestamp_format)?;
+                let template = config
+                    .name_template
+                    .as_deref()
+                    .unwrap_or(DEFAULT_NAME_TEMPLATE);
+                render_name_template(template, &name_base, created, &timestamp_format, |name| {
+                    config.checkpoints.contains_key(name) || alts_dir.join(name).exists()
+                })?
+            }
+        };
+
+        // Check if checkpoint name already exists in index
+        if config.checkpoints.contains_key(&checkpoint_name) && !(args.force && args.dry_run) {
+            return Err(failure(
+                FailureKind::AlreadyExists,
+                format!("Checkpoint name '{}' already exists", checkpoint_name),
+            ));
+        }
+        check_name_collision(&config, &checkpoint_name)?;
+
+        let checkpoint_path = alts_dir.join(&checkpoint_name);
+        // Generated names skip taken paths; a given one must not merge into
+        // data already there. `--force` has set an indexed checkpoint aside.
+        let replacing_in_dry_run = args.force && config.checkpoints.contains_key(&checkpoint_name);
+        if fs::symlink_metadata(&checkpoint_path).is_ok() && !replacing_in_dry_run {
+            return Err(failure(
+                FailureKind::AlreadyExists,
+                format!(
+                    "'{}' already exists in {} but is not an indexed checkpoint; remove it or run 'alts prune --orphans'",
+                    checkpoint_name, ALTS_DIR
+                ),
+            ));
+        }
+        if args.dry_run {
+            let planned = copy_dir_recursive(&target_path, &checkpoint_path, &options)?;
+            log_oversized(&planned);
+            for (path, reason) in &planned.failed {
+                warn!("Would leave out {}: {}", path.display(), reason);
+            }
+            info!(
+                "Dry run: checkpoint '{}' would copy {} file(s), {}",
+                checkpoint_name,
+                planned.files,
+                format_size(planned.bytes, SizeUnit::Auto)
+            );
+            return Ok(checkpoint_name);
+        }
+
+        info!("Creating checkpoint '{}'...", checkpoint_name);
+        // Claimed up front, so the cleanup below only ever removes this run's work
+        fs::create_dir(&checkpoint_path).with_context(|| {
+            format!(
+                "Failed to create checkpoint directory '{}'",
+                checkpoint_path.display()
+            )
+        })?;
+        let copied = match &args.from_archive {
+            Some(archive) => extract_archive(archive, &checkpoint_path),
+            None => copy_dir_recursive(&target_path, &checkpoint_path, &options),
+        };
+        let copied = match copied {
+            Ok(copied) => copied,
+            Err(e) => {
+                // Never leave a partial copy behind under a usable name
+                let _ = remove_path(&checkpoint_path);
+                return Err(e);
+            }
+        };
+        (checkpoint_name, copied, None)
+    };
+    let elapsed = started.elapsed();
+
+    if let Some(percent) = args.verify_sample {
+        let checkpoint_path = alts_dir.join(&checkpoint_name);
+        if let Err(e) = verify_sample(&target_path, &checkpoint_path, percent) {
+            remove_path(&checkpoint_path)?;
+            return Err(anyhow::anyhow!(
+                "{}; checkpoint '{}' was discarded",
+                e,
+                checkpoint_name
+            ));
+        }
+    }
+
+    if !copied.failed.is_empty() {
+        let checkpoint_path = alts_dir.join(&checkpoint_name);
+        if let Err(e) = write_errors_file(&checkpoint_path, &target_path, &copied.failed) {
+            remove_path(&checkpoint_path)?;
+            return Err(anyhow::anyhow!(
+                "{}; checkpoint '{}' was discarded",
+                e,
+                checkpoint_name
+            ));
+        }
+    }
+
+    // Hash before deltas or compression change what is stored
+    let checksums = match file_checksums(&alts_dir.join(&checkpoint_name)) {
+        Ok(checksums) => checksums,
+        Err(e) => {
+            remove_path(&alts_dir.join(&checkpoint_name))?;
+            return Err(anyhow::anyhow!(
+                "{}; checkpoint '{}' was discarded",
+                e,
+                checkpoint_name
+            ));
+        }
+    };
+
+    if let Some(base) = &delta_base {
+        let checkpoint_path = alts_dir.join(&checkpoint_name);
+        info!("Computing deltas against '{}'...", base);
+        let stored = with_materialized(&config, &alts_dir, base, |base_root| {
+            store_deltas(&checkpoint_path, base_root)
+        });
+        match stored {
+            Ok((files, saved)) => info!(
+                "Stored {} file(s) as deltas against '{}', saving {}",
+                files,
+                base,
+                format_size(saved, SizeUnit::Auto)
+            ),
+            Err(e) => {
+                remove_path(&checkpoint_path)?;
+                return Err(anyhow::anyhow!(
+                    "{}; checkpoint '{}' was discarded",
+                    e,
+                    checkpoint_name
+                ));
+            }
+        }
+    }
+
+    let stored_bytes = match store_checkpoint(storage, &alts_dir, &checkpoint_name, &checksums) {
+        Ok(size) => size,
+        Err(e) => {
+            return Err(anyhow::anyhow!(
+                "{}; checkpoint '{}' was discarded",
+                e,
+                checkpoint_name
+            ));
+        }
+    };
+
+    let mut tags = args.tags.clone();
+    if args.tag_from_branch
+        && let Some(branch) = git_branch(&target_path)
+        && !tags.contains(&branch)
+    {
+        tags.push(branch);
+    }
 
     // Add checkpoint to index
-    let now: DateTime<Utc> = Utc::now();
-    let timestamp = now.to_rfc3339();
+    let timestamp = created.to_rfc3339();
     config.checkpoints.insert(
         checkpoint_name.clone(),
         Checkpoint {
             timestamp: timestamp.clone(),
+            message,
+            tags,
+            hash,
+            delta_base,
+            storage,
+            created_by_version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            stored_size: Some(stored_bytes),
+            linked_from: link_base.clone().filter(|_| copied.linked > 0),
+            partial: !copied.failed.is_empty(),
+            source_dir,
         },
     );
     save_config(&config)?;
+    record_event(&alts_dir, "checkpoint", &[&checkpoint_name]);
+    if let Some(replaced) = replaced {
+        let storage = replaced.storage;
+        replaced.commit();
+        if storage == Storage::Objects {
+            collect_object_garbage(&config, &alts_dir)?;
+        }
+        info!("Replaced the previous checkpoint '{}'", checkpoint_name);
+    }
+
+    info!(
+        "Checkpoint '{}' created successfully: {} file(s), {}",
+        checkpoint_name,
+        copied.files,
+        format_transfer(copied.bytes, elapsed)
+    );
+
+    log_oversized(&copied);
+    if !copied.failed.is_empty() {
+        warn!(
+            "Left out {} item(s) that could not be copied; '{}' is marked partial and lists them in {}:",
+            copied.failed.len(),
+            checkpoint_name,
+            ERRORS_FILE
+        );
+        for (path, reason) in &copied.failed {
+            warn!("  {}: {}", path.display(), reason);
+        }
+    }
+
+    if let Some(base) = &link_base
+        && copied.linked > 0
+    {
+        info!("Linked {} unchanged file(s) from '{}'", copied.linked, base);
+    }
+
+    if args.verify {
+        info!(
+            "Verified {} file(s), {}",
+            copied.verified,
+            format_size(copied.bytes, SizeUnit::Auto)
+        );
+    }
+
+    if let Some(webhook) = &config.webhook {
+        notify_webhook(
+            webhook,
+            &CheckpointEvent {
+                event: "checkpoint",
+                name: &checkpoint_name,
+                timestamp: &timestamp,
+                size: copied.bytes,
+                host: gethostname::gethostname().to_string_lossy().into_owned(),
+            },
+        );
+    }
+
+    // The checkpoint itself succeeded; a failed cleanup must not hide that
+    if let Err(e) = apply_retention(&mut config, &alts_dir, &checkpoint_name) {
+        warn!("Retention policy not applied: {}", e);
+    }
+
+    Ok(checkpoint_name)
+}
+
+/// A checkpoint moved aside by `checkpoint --force`. Dropping it without
+/// `commit` moves it back, so a failed replacement keeps the old version.
+struct SetAside {
+    name: String,
+    storage: Storage,
+    /// Pairs of (original, backup) paths
+    moved: Vec<(PathBuf, PathBuf)>,
+    committed: bool,
+}
+
+impl SetAside {
+    /// Deletes the old version once the new one is in the index
+    fn commit(mut self) {
+        for (_, backup) in &self.moved {
+            if let Err(e) = remove_path(backup) {
+                warn!("Failed to remove '{}': {}", backup.display(), e);
+            }
+        }
+        self.committed = true;
+    }
+}
+
+impl Drop for SetAside {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+        for (original, backup) in &self.moved {
+            if fs::symlink_metadata(original).is_ok() {
+                let _ = remove_path(original);
+            }
+            if let Err(e) = fs::rename(backup, original) {
+                error!(
+                    "Failed to move '{}' back to '{}': {}",
+                    backup.display(),
+                    original.display(),
+                    e
+                );
+            }
+        }
+        info!("Kept the previous checkpoint '{}'", self.name);
+    }
+}
+
+/// Moves checkpoint `name`'s data aside and drops it from the in-memory
+/// index so a new checkpoint can take its name; `None` if there is none
+fn set_aside_checkpoint(
+    config: &mut Config,
+    alts_dir: &Path,
+    name: &str,
+) -> Result<Option<SetAside>> {
+    let Some(checkpoint) = config.checkpoints.get(name) else {
+        return Ok(None);
+    };
+    if let Some(dependent) = delta_dependent(config, name) {
+        return Err(anyhow::anyhow!(
+            "Cannot replace '{}': checkpoint '{}' stores deltas against it",
+            name,
+            dependent
+        ));
+    }
+    let mut set_aside = SetAside {
+        name: name.to_string(),
+        storage: checkpoint.storage,
+        moved: Vec::new(),
+        committed: false,
+    };
+    for original in [
+        checkpoint.storage_path(alts_dir, name),
+        checksums_path(alts_dir, name),
+    ] {
+        if fs::symlink_metadata(&original).is_err() {
+            continue;
+        }
+        let file_name = original.file_name().expect("checkpoint paths have a name");
+        let backup = alts_dir.join(format!(
+            ".replace-{}-{}",
+            std::process::id(),
+            file_name.to_string_lossy()
+        ));
+        fs::rename(&original, &backup)
+            .with_context(|| format!("Failed to move checkpoint '{}' aside", name))?;
+        set_aside.moved.push((original, backup));
+    }
+    config.checkpoints.remove(name);
+    // Links stay valid, but the name will refer to different content
+    for other in config.checkpoints.values_mut() {
+        if other.linked_from.as_deref() == Some(name) {
+            other.linked_from = None;
+        }
+    }
+    Ok(Some(set_aside))
+}
+
+/// Records the entries `--skip-errors` left out in the checkpoint's
+/// `ERRORS_FILE`, with paths relative to the target where possible
+fn write_errors_file(
+    checkpoint_path: &Path,
+    target_path: &Path,
+    failed: &[(PathBuf, String)],
+) -> Result<()> {
+    let content: String = failed
+        .iter()
+        .map(|(path, reason)| {
+            let path = path.strip_prefix(target_path).unwrap_or(path);
+            format!("{}: {}\n", path.display(), reason)
+        })
+        .collect();
+    fs::write(checkpoint_path.join(ERRORS_FILE), content)
+        .with_context(|| format!("Failed to write {}", ERRORS_FILE))
+}
+
+/// The lines of a partial checkpoint's `ERRORS_FILE`; empty for complete
+/// checkpoints
+fn skipped_entries(config: &Config, alts_dir: &Path, name: &str) -> Result<Vec<String>> {
+    let (checkpoint, _) = find_checkpoint(config, alts_dir, name)?;
+    if !checkpoint.partial {
+        return Ok(Vec::new());
+    }
+    with_materialized(config, alts_dir, name, |root| {
+        let content = fs::read_to_string(root.join(ERRORS_FILE))
+            .with_context(|| format!("Failed to read {} of '{}'", ERRORS_FILE, name))?;
+        Ok(content.lines().map(str::to_string).collect())
+    })
+}
+
+fn log_oversized(stats: &CopyStats) {
+    if stats.oversized.is_empty() {
+        return;
+    }
+    info!(
+        "Left out {} file(s) larger than max_file_size:",
+        stats.oversized.len()
+    );
+    for (path, size) in &stats.oversized {
+        info!(
+            "  {} ({})",
+            path.display(),
+            format_size(*size, SizeUnit::Auto)
+        );
+    }
+}
+
+/// The configured `max_file_size` in bytes
+fn max_file_size(config: &Config) -> Result<Option<u64>> {
+    config
+        .max_file_size
+        .as_deref()
+        .map(|size| parse_size(size).with_context(|| format!("Invalid max_file_size '{}'", size)))
+        .transpose()
+}
+
+/// Fails before anything is written when the `planned` copy does not fit on
+/// the volume holding `.alts`
+fn ensure_free_space(alts_dir: &Path, planned: &CopyStats) -> Result<()> {
+    let free = match fs4::available_space(alts_dir) {
+        Ok(free) => free,
+        Err(e) => {
+            debug!("Skipping free space check: {}", e);
+            return Ok(());
+        }
+    };
+    if planned.bytes > free {
+        return Err(failure(
+            FailureKind::NoSpace,
+            format!(
+                "Not enough free space for a checkpoint: it needs {} but only {} is available",
+                format_size(planned.bytes, SizeUnit::Auto),
+                format_size(free, SizeUnit::Auto)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The newest checkpoint of `source`, by timestamp, stored as a plain
+/// directory without deltas, which incremental checkpoints link unchanged
+/// files from
+fn newest_plain_checkpoint(config: &Config, alts_dir: &Path, source: &str) -> Option<String> {
+    config
+        .checkpoints
+        .iter()
+        .filter(|(name, checkpoint)| {
+            checkpoint.source(config) == source
+                && checkpoint.storage == Storage::Dir
+                && checkpoint.delta_base.is_none()
+                && checkpoint.storage_path(alts_dir, name).is_dir()
+        })
+        .max_by(|a, b| a.1.timestamp.cmp(&b.1.timestamp))
+        .map(|(name, _)| name.clone())
+}
+
+/// Storage format for new checkpoints according to the config
+fn configured_storage(config: &Config) -> Result<Storage> {
+    match (config.dedup, config.compression) {
+        (true, Some(Storage::TarGz)) => Err(anyhow::anyhow!(
+            "'dedup' and 'compression' cannot both be set in {}",
+            CONFIG_FILE
+        )),
+        (true, _) => Ok(Storage::Objects),
+        (false, compression) => Ok(compression.unwrap_or_default()),
+    }
+}
+
+/// Converts the freshly written directory `.alts/<name>` to `storage` and
+/// writes its checksum sidecar, returning the stored size. On failure
+/// nothing of the checkpoint is left.
+fn store_checkpoint(
+    storage: Storage,
+    alts_dir: &Path,
+    name: &str,
+    checksums: &FileChecksums,
+) -> Result<u64> {
+    let checkpoint_path = alts_dir.join(name);
+    let archive = alts_dir.join(format!("{}.tar.gz", name));
+    let stored = match storage {
+        Storage::Dir => Ok(()),
+        Storage::TarGz => pack_checkpoint(&checkpoint_path, &archive)
+            .map(|size| info!("Compressed to {}", format_size(size, SizeUnit::Auto))),
+        Storage::Objects => {
+            store_objects(&checkpoint_path, &alts_dir.join(OBJECTS_DIR)).map(|(reused, saved)| {
+                info!(
+                    "Deduplicated {} file(s) already in the object store, saving {}",
+                    reused,
+                    format_size(saved, SizeUnit::Auto)
+                )
+            })
+        }
+    };
+    let stored = stored.and_then(|_| {
+        let content = serde_json::to_string_pretty(checksums)?;
+        fs::write(checksums_path(alts_dir, name), content)
+            .context("Failed to write checksum manifest")?;
+        stored_size(&if storage == Storage::TarGz {
+            archive.clone()
+        } else {
+            checkpoint_path.clone()
+        })
+    });
+    if stored.is_err() {
+        // Whichever form the checkpoint had reached
+        let _ = remove_path(&checkpoint_path);
+        let _ = remove_path(&archive);
+    }
+    stored
+}
+
+/// Deletes checkpoints beyond `max_checkpoints` or older than `max_age_days`,
+/// oldest first by their stored timestamp. `created` is never removed.
+fn apply_retention(config: &mut Config, alts_dir: &Path, created: &str) -> Result<()> {
+    if config.max_checkpoints.is_none() && config.max_age_days.is_none() {
+        return Ok(());
+    }
+
+    let mut dated = Vec::new();
+    for (name, checkpoint) in &config.checkpoints {
+        match DateTime::parse_from_rfc3339(&checkpoint.timestamp) {
+            Ok(time) => dated.push((time, name.clone())),
+            Err(_) => warn!(
+                "Retention: keeping '{}': unparseable timestamp '{}'",
+                name, checkpoint.timestamp
+            ),
+        }
+    }
+    // Newest first
+    dated.sort_by_key(|&(time, _)| std::cmp::Reverse(time));
+
+    let cutoff = config
+        .max_age_days
+        .map(|days| Utc::now() - chrono::Duration::days(days as i64));
+    let mut doomed = Vec::new();
+    for (index, (time, name)) in dated.into_iter().enumerate() {
+        let reason = if config.max_checkpoints.is_some_and(|max| index >= max) {
+            "beyond max_checkpoints"
+        } else if cutoff.is_some_and(|cutoff| time < cutoff) {
+            "older than max_age_days"
+        } else {
+            continue;
+        };
+        if name != created {
+            doomed.push((name, reason));
+        }
+    }
+    if doomed.is_empty() {
+        return Ok(());
+    }
+
+    for (name, reason) in doomed.iter().rev() {
+        if let Some(dependent) = delta_dependent(config, name) {
+            info!(
+                "Retention: keeping '{}': '{}' stores deltas against it",
+                name, dependent
+            );
+            continue;
+        }
+        delete_checkpoint(config, alts_dir, name)?;
+        info!("Retention: auto-removed '{}' ({})", name, reason);
+    }
+    save_config(config)
+}
+
+/// Delivers an event to the configured webhook; failures are only logged so
+/// they never undo a checkpoint
+fn notify_webhook(webhook: &Webhook, event: &CheckpointEvent) {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(WEBHOOK_TIMEOUT))
+        .build()
+        .into();
+    let mut request = agent.post(&webhook.url);
+    if let Some(authorization) = &webhook.authorization {
+        request = request.header("Authorization", authorization);
+    }
+    match request.send_json(event) {
+        Ok(_) => debug!("Notified webhook {}", webhook.url),
+        Err(e) => warn!("Failed to notify webhook {}: {}", webhook.url, e),
+    }
+}
+
+/// The time recorded for a new checkpoint. `$SOURCE_DATE_EPOCH` takes
+/// precedence, as in reproducible-build tooling; `--no-timestamps` otherwise
+/// pins it to the Unix epoch.
+fn creation_time(no_timestamps: bool) -> Result<DateTime<Utc>> {
+    if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") {
+        return epoch
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .ok_or_else(|| anyhow::anyhow!("Invalid SOURCE_DATE_EPOCH '{}'", epoch));
+    }
+    Ok(if no_timestamps {
+        DateTime::UNIX_EPOCH
+    } else {
+        Utc::now()
+    })
+}
+
+/// Builds a checkpoint name from the target's name and `suffix`
+fn generated_name(target_dir: &str, suffix: &str) -> String {
+    // Handle file extensions correctly - insert suffix before extension
+    let (file_stem, extension) = stem_and_extension(target_dir);
+    format!("{}_{}{}", file_stem, suffix, extension)
+}
+
+/// Splits a target name into its stem and its extension with the dot, if any
+fn stem_and_extension(target_dir: &str) -> (&str, String) {
+    let target_path = Path::new(target_dir);
+    let file_stem = target_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(target_dir);
+    let extension = target_path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|ext| format!(".{}", ext))
+        .unwrap_or_default();
+    (file_stem, extension)
+}
+
+/// Fills in a `name_template`. `{stem}` and `{ext}` come from the target's
+/// name, `{timestamp}` uses the timestamp format, `{year}`, `{month}`,
+/// `{day}`, `{date}` (YYYY-MM-DD) and `{time}` (HHMMSS) the UTC creation
+/// time, and `{n}` the lowest number from 1 giving a name not yet `taken`.
+fn render_name_template(
+    template: &str,
+    target_dir: &str,
+    created: DateTime<Utc>,
+    timestamp_format: &str,
+    taken: impl Fn(&str) -> bool,
+) -> Result<String> {
+    let (stem, extension) = stem_and_extension(target_dir);
+    // `{n}` is left in place until everything else is known
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let Some(length) = rest[start..].find('}') else {
+            return Err(anyhow::anyhow!(
+                "Unclosed '{{' in name template '{}'",
+                template
+            ));
+        };
+        let placeholder = &rest[start + 1..start + length];
+        let value = match placeholder {
+            "stem" => stem.to_string(),
+            "ext" => extension.clone(),
+            "timestamp" => created.format(timestamp_format).to_string(),
+            "year" => created.format("%Y").to_string(),
+            "month" => created.format("%m").to_string(),
+            "day" => created.format("%d").to_string(),
+            "date" => created.format("%Y-%m-%d").to_string(),
+            "time" => created.format("%H%M%S").to_string(),
+            "n" => "{n}".to_string(),
+            other => {
+                return Err(anyhow::anyhow!(
+                    "Unknown placeholder '{{{}}}' in name template '{}'; use stem, ext, timestamp, year, month, day, date, time or n",
+                    other,
+                    template
+                ));
+            }
+        };
+        rendered.push_str(&value);
+        rest = &rest[start + length + 1..];
+    }
+    rendered.push_str(rest);
+
+    let name = if rendered.contains("{n}") {
+        (1..)
+            .map(|n| rendered.replace("{n}", &n.to_string()))
+            .find(|name| !taken(name))
+            .expect("sequence numbers are unbounded")
+    } else {
+        rendered
+    };
+    if name.contains(['/', '\\']) {
+        return Err(anyhow::anyhow!(
+            "Name template '{}' must not produce path separators (got '{}')",
+            template,
+            name
+        ));
+    }
+    validate_checkpoint_name(&name)
+}
+
+fn parse_percent(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => Ok(percent),
+        _ => Err(format!("'{}' is not a percentage between 0 and 100", s)),
+    }
+}
+
+/// Hashes a random `percent` of the files in a fresh checkpoint and compares
+/// them with their counterparts in `source`. At least one file is checked
+/// whenever the percentage is non-zero.
+fn verify_sample(source: &Path, checkpoint_path: &Path, percent: f64) -> Result<()> {
+    let files: Vec<PathBuf> = collect_tree(checkpoint_path)?
+        .into_iter()
+        .filter(|(_, entry)| matches!(entry, TreeEntry::File { .. }))
+        .map(|(path, _)| path)
+        .collect();
+    let sample_size = ((files.len() as f64 * percent / 100.0).ceil() as usize).min(files.len());
+    let sample = rand::seq::index::sample(&mut rand::rng(), files.len(), sample_size);
+
+    info!(
+        "Verifying a sample of {} of {} file(s)...",
+        sample_size,
+        files.len()
+    );
+    let mismatched = sample
+        .into_vec()
+        .into_par_iter()
+        .map(|i| {
+            let path = &files[i];
+            let same = hash_file(&source.join(path))? == hash_file(&checkpoint_path.join(path))?;
+            Ok((!same).then_some(path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mismatched: Vec<&PathBuf> = mismatched.into_iter().flatten().collect();
+
+    if !mismatched.is_empty() {
+        for path in &mismatched {
+            error!("  {}: differs from source", path.display());
+        }
+        return Err(anyhow::anyhow!(
+            "Sample verification failed: {} of {} sampled file(s) differ",
+            mismatched.len(),
+            sample_size
+        ));
+    }
+    info!("Sample verification passed");
+    Ok(())
+}
+
+/// Unpacks an archive into a new checkpoint directory, removing it again if
+/// anything fails. Entries that would land outside `dst` are rejected.
+fn extract_archive(archive: &Path, dst: &Path) -> Result<CopyStats> {
+    let file_name = archive.to_string_lossy().to_lowercase();
+    let file = fs::File::open(archive)
+        .with_context(|| format!("Failed to open archive '{}'", archive.display()))?;
+
+    fs::create_dir_all(dst).context("Failed to create directory")?;
+    let result = if file_name.ends_with(".zip") {
+        extract_zip(file, dst)
+    } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+        extract_tar(flate2::read::GzDecoder::new(file), dst)
+    } else if file_name.ends_with(".tar") {
+        extract_tar(file, dst)
+    } else {
+        Err(anyhow::anyhow!(
+            "Unsupported archive '{}'; expected .tar, .tar.gz, .tgz or .zip",
+            archive.display()
+        ))
+    };
+
+    if result.is_err() {
+        let _ = remove_path(dst);
+    }
+    result.map_err(|e| anyhow::anyhow!("Failed to extract '{}': {}", archive.display(), e))
+}
+
+/// Replaces the checkpoint directory `dir` by a gzip-compressed tarball at
+/// `archive`, returning the compressed size
+fn pack_checkpoint(dir: &Path, archive: &Path) -> Result<u64> {
+    let packed = (|| -> Result<()> {
+        let file = fs::File::create(archive)?;
+        let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+        builder.append_dir_all(".", dir)?;
+        builder.into_inner()?.finish()?.sync_all()?;
+        Ok(())
+    })();
+    if let Err(e) = packed {
+        let _ = fs::remove_file(archive);
+        return Err(e.context(format!("Failed to write '{}'", archive.display())));
+    }
+
+    remove_path(dir).context("Failed to remove uncompressed checkpoint")?;
+    Ok(fs::metadata(archive)?.len())
+}
+
+/// Location of the object with content hash `hash` in the object store
+fn object_path(objects_dir: &Path, hash: &str) -> PathBuf {
+    objects_dir.join(&hash[..2]).join(&hash[2..])
+}
+
+/// Moves every file of the checkpoint directory `dir` into the object store
+/// (unless an identical one is there already) and leaves only a manifest
+/// behind. Returns how many files were already stored and their size.
+fn store_objects(dir: &Path, objects_dir: &Path) -> Result<(u64, u64)> {
+    let mut manifest = Manifest {
+        root: FileMeta::read(dir)?,
+        ..Manifest::default()
+    };
+    let mut reused = 0;
+    let mut saved = 0;
+    for (path, entry) in collect_tree(dir)? {
+        let entry = match entry {
+            TreeEntry::Dir => ManifestEntry::Dir {
+                meta: FileMeta::read(&dir.join(&path))?,
+            },
+            TreeEntry::Symlink(target) => ManifestEntry::Symlink { target },
+            TreeEntry::File { size } => {
+                let source = dir.join(&path);
+                let meta = FileMeta::read(&source)?;
+                let hash = hash_file(&source)?;
+                let object = object_path(objects_dir, &hash);
+                if object.exists() {
+                    reused += 1;
+                    saved += size;
+                } else {
+                    if let Some(parent) = object.parent() {
+                        fs::create_dir_all(parent).context("Failed to create object directory")?;
+                    }
+                    fs::rename(&source, &object)
+                        .with_context(|| format!("Failed to store '{}'", source.display()))?;
+                }
+                ManifestEntry::File { hash, meta }
+            }
+        };
+        manifest
+            .entries
+            .insert(path.to_string_lossy().into_owned(), entry);
+    }
+
+    // Not part of the tree, so kept as is beside the manifest
+    let errors = fs::read(dir.join(ERRORS_FILE)).ok();
+    remove_path(dir).context("Failed to remove copied files")?;
+    fs::create_dir(dir).context("Failed to create checkpoint directory")?;
+    let content = toml::to_string_pretty(&manifest).context("Failed to serialize manifest")?;
+    fs::write(dir.join(MANIFEST_FILE), content).context("Failed to write manifest")?;
+    if let Some(errors) = errors {
+        fs::write(dir.join(ERRORS_FILE), errors)
+            .with_context(|| format!("Failed to write {}", ERRORS_FILE))?;
+    }
+    Ok((reused, saved))
+}
+
+fn read_manifest(dir: &Path) -> Result<Manifest> {
+    let path = dir.join(MANIFEST_FILE);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("Failed to parse '{}'", path.display()))
+}
+
+/// Rebuilds the tree described by the manifest in `dir` at `dst`
+fn restore_objects(dir: &Path, objects_dir: &Path, dst: &Path) -> Result<()> {
+    fs::create_dir_all(dst).context("Failed to create directory")?;
+    let manifest = read_manifest(dir)?;
+    // Directory metadata is applied last, once nothing more is written inside
+    let mut dirs = vec![(dst.to_path_buf(), manifest.root)];
+    // Sorted paths put every directory before its content
+    for (path, entry) in manifest.entries {
+        let target = dst.join(&path);
+        match entry {
+            ManifestEntry::Dir { meta } => {
+                fs::create_dir_all(&target).context("Failed to create directory")?;
+                dirs.push((target, meta));
+            }
+            ManifestEntry::File { hash, meta } => {
+                fs::copy(object_path(objects_dir, &hash), &target).with_context(|| {
+                    format!("Failed to restore '{}' from object {}", path, hash)
+                })?;
+                meta.apply(&target)?;
+            }
+            ManifestEntry::Symlink { target: link } => {
+                let is_dir = target.parent().is_some_and(|p| p.join(&link).is_dir());
+                create_symlink(&link, &target, is_dir).context("Failed to create symlink")?;
+            }
+        }
+    }
+    let errors = dir.join(ERRORS_FILE);
+    if errors.exists() {
+        fs::copy(&errors, dst.join(ERRORS_FILE))
+            .with_context(|| format!("Failed to restore {}", ERRORS_FILE))?;
+    }
+    for (path, meta) in dirs.iter().rev() {
+        meta.apply(path)?;
+    }
+    Ok(())
+}
+
+/// Removes objects no deduplicated checkpoint in the index refers to
+fn collect_object_garbage(config: &Config, alts_dir: &Path) -> Result<()> {
+    let objects_dir = alts_dir.join(OBJECTS_DIR);
+    if !objects_dir.exists() {
+        return Ok(());
+    }
+    let mut referenced = BTreeSet::new();
+    for (name, checkpoint) in &config.checkpoints {
+        if checkpoint.storage != Storage::Objects {
+            continue;
+        }
+        // Without every manifest we cannot tell what is still in use
+        let manifest =
+            read_manifest(&checkpoint.storage_path(alts_dir, name)).with_context(|| {
+                format!("Not collecting objects: manifest of '{}' unreadable", name)
+            })?;
+        for entry in manifest.entries.into_values() {
+            if let ManifestEntry::File { hash, .. } = entry {
+                referenced.insert(hash);
+            }
+        }
+    }
+
+    let mut removed = 0;
+    for prefix in fs::read_dir(&objects_dir).context("Failed to read object store")? {
+        let prefix = prefix?;
+        let prefix_name = prefix.file_name().to_string_lossy().into_owned();
+        for object in fs::read_dir(prefix.path()).context("Failed to read object store")? {
+            let object = object?;
+            let hash = format!("{}{}", prefix_name, object.file_name().to_string_lossy());
+            if !referenced.contains(&hash) {
+                remove_path(&object.path())?;
+                removed += 1;
+            }
+        }
+        // Only succeeds once the prefix directory is empty
+        let _ = fs::remove_dir(prefix.path());
+    }
+    if removed > 0 {
+        info!("Removed {} unreferenced object(s)", removed);
+    }
+    Ok(())
+}
+
+/// Whether an archive entry path stays inside the directory it is extracted to
+fn is_contained(path: &Path) -> bool {
+    path.components().all(|c| {
+        matches!(
+            c,
+            std::path::Component::Normal(_) | std::path::Component::CurDir
+        )
+    })
+}
+
+fn extract_tar(reader: impl Read, dst: &Path) -> Result<CopyStats> {
+    let mut stats = CopyStats::default();
+    let mut archive = tar::Archive::new(reader);
+    // Directories are unpacked writable and get their recorded metadata at the end
+    let mut dirs = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !is_contained(&path) {
+            return Err(anyhow::anyhow!(
+                "Entry '{}' would escape the checkpoint directory",
+                path.display()
+            ));
+        }
+
+        debug!("Extracting: {}", path.display());
+        let header = entry.header();
+        let is_file = header.entry_type().is_file();
+        let dir_meta = header.entry_type().is_dir().then(|| FileMeta {
+            mode: header.mode().ok().map(|mode| mode & 0o7777),
+            mtime_ns: header
+                .mtime()
+                .ok()
+                .and_then(|secs| i64::try_from(secs).ok()?.checked_mul(1_000_000_000)),
+        });
+        let size = entry.size();
+        // `unpack_in` also refuses to write through symlinks unpacked earlier
+        if !entry.unpack_in(dst)? {
+            return Err(anyhow::anyhow!(
+                "Entry '{}' would escape the checkpoint directory",
+                path.display()
+            ));
+        }
+        if let Some(meta) = dir_meta {
+            let dir = dst.join(&path);
+            make_dirs_writable(&dir)?;
+            dirs.push((dir, meta));
+        }
+        if is_file {
+            stats.files += 1;
+            stats.bytes += size;
+        }
+    }
+    for (dir, meta) in dirs.iter().rev() {
+        meta.apply(dir)?;
+    }
+    Ok(stats)
+}
+
+/// Unpacks a zip archive like `extract_tar` does a tarball: symlinks are
+/// recreated, Unix permission bits restored, and directories get theirs last
+fn extract_zip(file: fs::File, dst: &Path) -> Result<CopyStats> {
+    let mut stats = CopyStats::default();
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut dirs = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let escapes = || {
+            anyhow::anyhow!(
+                "Entry '{}' would escape the checkpoint directory",
+                String::from_utf8_lossy(entry.name_raw())
+            )
+        };
+        let path = entry
+            .enclosed_name()
+            .filter(|path| is_contained(path))
+            .ok_or_else(escapes)?;
+        // Like `unpack_in`, never write through a symlink unpacked earlier
+        if path
+            .ancestors()
+            .skip(1)
+            .any(|parent| fs::symlink_metadata(dst.join(parent)).is_ok_and(|m| m.is_symlink()))
+        {
+            return Err(escapes());
+        }
+
+        debug!("Extracting: {}", path.display());
+        let out_path = dst.join(&path);
+        let meta = FileMeta {
+            mode: entry.unix_mode().map(|mode| mode & 0o7777),
+            mtime_ns: None,
+        };
+        if entry.is_dir() {
+            fs::create_dir_all(&out_path).context("Failed to create directory")?;
+            dirs.push((out_path, meta));
+            continue;
+        }
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create directory")?;
+        }
+        if entry.is_symlink() {
+            let mut target = String::new();
+            entry
+                .read_to_string(&mut target)
+                .with_context(|| format!("Failed to read link target of '{}'", path.display()))?;
+            create_symlink(Path::new(&target), &out_path, false)
+                .with_context(|| format!("Failed to create symlink '{}'", out_path.display()))?;
+            continue;
+        }
+        let mut out = fs::File::create(&out_path)
+            .with_context(|| format!("Failed to create '{}'", out_path.display()))?;
+        stats.bytes += std::io::copy(&mut entry, &mut out)?;
+        stats.files += 1;
+        meta.apply(&out_path)?;
+    }
+    for (dir, meta) in dirs.iter().rev() {
+        meta.apply(dir)?;
+    }
+    Ok(stats)
+}
+
+/// Current branch of the git repository containing `target`. Problems are
+/// only warned about, since the tag is a convenience.
+fn git_branch(target: &Path) -> Option<String> {
+    let output = match std::process::Command::new("git")
+        .arg("-C")
+        .arg(target)
+        .args(["rev-parse", "--abbrev-ref", "HEAD"])
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) => {
+            warn!("Not tagging with a branch: failed to run git: {}", e);
+            return None;
+        }
+    };
+    if !output.status.success() {
+        warn!(
+            "Not tagging with a branch: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return None;
+    }
+
+    let branch = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if branch == "HEAD" {
+        warn!("Not tagging with a branch: HEAD is detached");
+        return None;
+    }
+    Some(branch)
+}
+
+/// `path` with the delta suffix appended to its file name
+fn delta_path(path: &Path) -> PathBuf {
+    let mut delta = path.as_os_str().to_owned();
+    delta.push(DELTA_SUFFIX);
+    PathBuf::from(delta)
+}
+
+/// Replaces each file in `root` that also exists in `base_root` by a bsdiff
+/// patch when that is smaller. Returns the number of files replaced and the
+/// bytes saved.
+fn store_deltas(root: &Path, base_root: &Path) -> Result<(u64, u64)> {
+    let files: Vec<PathBuf> = collect_tree(root)?
+        .into_iter()
+        .filter(|(path, entry)| {
+            matches!(entry, TreeEntry::File { .. })
+                && fs::symlink_metadata(base_root.join(path)).is_ok_and(|m| m.is_file())
+        })
+        .map(|(path, _)| path)
+        .collect();
+
+    let saved = files
+        .par_iter()
+        .map(|rel| {
+            let path = root.join(rel);
+            let target = fs::read(&path)?;
+            let source = fs::read(base_root.join(rel))?;
+            let mut patch = Vec::new();
+            qbsdiff::Bsdiff::new(&source, &target)
+                .compare(std::io::Cursor::new(&mut patch))
+                .with_context(|| format!("Failed to diff '{}'", rel.display()))?;
+            if patch.len() >= target.len() {
+                return Ok(None);
+            }
+
+            debug!("Storing delta: {}", rel.display());
+            let delta = delta_path(&path);
+            fs::write(&delta, &patch).context("Failed to write delta")?;
+            fs::set_permissions(&delta, fs::metadata(&path)?.permissions())?;
+            fs::remove_file(&path)?;
+            Ok(Some((target.len() - patch.len()) as u64))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let saved: Vec<u64> = saved.into_iter().flatten().collect();
+    Ok((saved.len() as u64, saved.iter().sum()))
+}
+
+/// Turns every delta file in `root` back into the full file by patching the
+/// corresponding file of the (materialized) base
+fn apply_deltas(root: &Path, base_root: &Path) -> Result<()> {
+    for (rel, entry) in collect_tree(root)? {
+        let TreeEntry::File { .. } = entry else {
+            continue;
+        };
+        let Some(original) = rel.to_str().and_then(|rel| rel.strip_suffix(DELTA_SUFFIX)) else {
+            continue;
+        };
+
+        let delta = root.join(&rel);
+        let patch = fs::read(&delta)?;
+        let source = fs::read(base_root.join(original))
+            .with_context(|| format!("Base file for delta '{}' is missing", rel.display()))?;
+        let mut target = Vec::new();
+        qbsdiff::Bspatch::new(&patch)
+            .and_then(|patcher| patcher.apply(&source, std::io::Cursor::new(&mut target)))
+            .with_context(|| format!("Failed to apply delta '{}'", rel.display()))?;
+
+        let path = root.join(original);
+        fs::write(&path, &target)?;
+        fs::set_permissions(&path, fs::metadata(&delta)?.permissions())?;
+        fs::remove_file(&delta)?;
+    }
+    Ok(())
+}
+
+/// Runs `f` on the full content of a checkpoint. Compressed and delta
+/// checkpoints are rebuilt into a temporary directory under `.alts` for the
+/// duration.
+fn with_materialized<T>(
+    config: &Config,
+    alts_dir: &Path,
+    name: &str,
+    f: impl FnOnce(&Path) -> Result<T>,
+) -> Result<T> {
+    let (checkpoint, checkpoint_path) = find_checkpoint(config, alts_dir, name)?;
+    if checkpoint.storage == Storage::Dir && checkpoint.delta_base.is_none() {
+        return f(&checkpoint_path);
+    }
+
+    let staging = alts_dir.join(format!(".materialize-{}-{}", std::process::id(), name));
+    let result = materialize(config, alts_dir, name, &staging).and_then(|_| f(&staging));
+    let _ = remove_path(&staging);
+    result
+}
+
+/// Writes the full content of a checkpoint to `dst`, unpacking compressed
+/// storage and following delta bases
+fn materialize(config: &Config, alts_dir: &Path, name: &str, dst: &Path) -> Result<()> {
+    let (checkpoint, checkpoint_path) = find_checkpoint(config, alts_dir, name)?;
+    match checkpoint.storage {
+        Storage::Dir => {
+            copy_dir_recursive(&checkpoint_path, dst, &CopyOptions::default())?;
+        }
+        Storage::TarGz => {
+            let file = fs::File::open(&checkpoint_path)
+                .with_context(|| format!("Failed to open '{}'", checkpoint_path.display()))?;
+            fs::create_dir_all(dst).context("Failed to create directory")?;
+            extract_tar(flate2::read::GzDecoder::new(file), dst)?;
+        }
+        Storage::Objects => {
+            restore_objects(&checkpoint_path, &alts_dir.join(OBJECTS_DIR), dst)?;
+        }
+    }
+    if let Some(base) = &checkpoint.delta_base {
+        debug!("Applying deltas of '{}' against '{}'", name, base);
+        with_materialized(config, alts_dir, base, |base_root| {
+            apply_deltas(dst, base_root)
+        })?;
+    }
+    Ok(())
+}
+
+/// Lists the files git tracks in `target`, relative to it, together with every
+/// directory leading to them
+fn git_tracked_paths(target: &Path) -> Result<BTreeSet<PathBuf>> {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(target)
+        .args(["ls-files", "-z"])
+        .output()
+        .context("Failed to run git; is it installed?")?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "'{}' is not inside a git repository: {}",
+            target.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let listing = String::from_utf8(output.stdout).context("git ls-files printed invalid UTF-8")?;
+    let mut paths = BTreeSet::new();
+    for file in listing.split('\0').filter(|f| !f.is_empty()) {
+        let mut path = PathBuf::from(file);
+        loop {
+            paths.insert(path.clone());
+            if !path.pop() || path.as_os_str().is_empty() {
+                break;
+            }
+        }
+    }
+    Ok(paths)
+}
+
+/// Copies the target into a staging directory, hashes it and moves it to a
+/// name derived from the hash. When a checkpoint with the same content already
+/// exists, its name is returned without copy stats.
+fn content_named_checkpoint(
+    config: &Config,
+    alts_dir: &Path,
+    target_path: &Path,
+    options: &CopyOptions,
+) -> Result<(String, Option<(CopyStats, String)>)> {
+    let prefix_len = config.hash_prefix_len.unwrap_or(DEFAULT_HASH_PREFIX_LEN);
+    if !(4..=64).contains(&prefix_len) {
+        return Err(anyhow::anyhow!(
+            "hash_prefix_len must be between 4 and 64, got {}",
+            prefix_len
+        ));
+    }
+
+    let staging_path = alts_dir.join(format!(".staging-{}", std::process::id()));
+    info!("Copying into staging area to compute content hash...");
+    let staged = copy_dir_recursive(target_path, &staging_path, options)
+        .and_then(|copied| Ok((copied, tree_hash(&staging_path)?)));
+    let (copied, hash) = match staged {
+        Ok(staged) => staged,
+        Err(e) => {
+            let _ = remove_path(&staging_path);
+            return Err(e);
+        }
+    };
+
+    if let Some((existing, _)) = config
+        .checkpoints
+        .iter()
+        .find(|(_, c)| c.hash.as_deref() == Some(hash.as_str()))
+    {
+        remove_path(&staging_path).context("Failed to remove staging directory")?;
+        info!(
+            "Content is identical to existing checkpoint '{}', nothing to do",
+            existing
+        );
+        return Ok((existing.clone(), None));
+    }
+
+    // Lengthen the prefix until it no longer clashes with another checkpoint
+    let name = (prefix_len..=hash.len())
+        .map(|len| hash[..len].to_string())
+        .find(|candidate| {
+            !config.checkpoints.contains_key(candidate) && !alts_dir.join(candidate).exists()
+        })
+        .ok_or_else(|| anyhow::anyhow!("No free name found for content hash {}", hash))?;
+
+    fs::rename(&staging_path, alts_dir.join(&name))
+        .context("Failed to move staged checkpoint into place")?;
+    info!("Creating checkpoint '{}'...", name);
+    Ok((name, Some((copied, hash))))
+}
+
+fn watch(interval: u64, on_change: Option<&str>) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let target_path = current_dir.join(&config.target_dir);
+
+    info!(
+        "Watching '{}' every {}s, press Ctrl-C to stop",
+        config.target_dir, interval
+    );
+    let fingerprint =
+        || tree_fingerprint(&target_path, &target_copy_options(&config, &target_path)?);
+    let mut last = fingerprint()?;
+
+    loop {
+        std::thread::sleep(Duration::from_secs(interval));
+
+        // Options are re-read so edits to `.altsignore` take effect
+        let current = match fingerprint() {
+            Ok(fingerprint) => fingerprint,
+            Err(e) => {
+                warn!("Failed to scan target directory: {}", e);
+                continue;
+            }
+        };
+        if current == last {
+            continue;
+        }
+        last = current;
+
+        info!("Change detected, creating checkpoint");
+        let name = match checkpoint(&CheckpointArgs::default()) {
+            Ok(name) => name,
+            Err(e) => {
+                error!("Automatic checkpoint failed: {}", e);
+                continue;
+            }
+        };
+
+        if let Some(command) = on_change {
+            run_on_change(command, &name);
+        }
+    }
+}
+
+/// Runs a user hook after an automatic checkpoint; failures never stop the watcher
+fn run_on_change(command: &str, checkpoint_name: &str) {
+    info!("Running on-change command: {}", command);
+
+    #[cfg(windows)]
+    let mut shell = {
+        let mut c = std::process::Command::new("cmd");
+        c.arg("/C");
+        c
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut c = std::process::Command::new("sh");
+        c.arg("-c");
+        c
+    };
+
+    match shell
+        .arg(command)
+        .env("ALTS_CHECKPOINT", checkpoint_name)
+        .output()
+    {
+        Ok(output) => {
+            debug!("stdout: {}", String::from_utf8_lossy(&output.stdout));
+            debug!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+            if !output.status.success() {
+                warn!("On-change command exited with {}", output.status);
+            }
+        }
+        Err(e) => warn!("Failed to run on-change command: {}", e),
+    }
+}
+
+/// Cheap summary of the paths, sizes and mtimes a checkpoint of `root` with
+/// `options` would copy, used to notice changes. Excluded paths, oversized
+/// files and `.alts` directories are left out, so a repository inside its
+/// own target does not see its checkpoints as changes.
+fn tree_fingerprint(root: &Path, options: &CopyOptions) -> Result<u64> {
+    fn walk(
+        root: &Path,
+        dir: &Path,
+        options: &CopyOptions,
+        excludes: &Gitignore,
+        hasher: &mut impl Hasher,
+    ) -> Result<()> {
+        let mut entries = fs::read_dir(dir)
+            .context("Failed to read directory")?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let metadata = path.symlink_metadata()?;
+            let rel = path.strip_prefix(root).unwrap_or(&path);
+            let skipped = (metadata.is_dir() && entry.file_name() == ALTS_DIR)
+                || excludes.matched(rel, metadata.is_dir()).is_ignore()
+                || (metadata.is_file()
+                    && options
+                        .max_file_size
+                        .is_some_and(|max| metadata.len() > max));
+            if skipped {
+                continue;
+            }
+            entry.file_name().hash(hasher);
+            metadata.len().hash(hasher);
+            metadata.modified().ok().hash(hasher);
+            if metadata.is_dir() {
+                walk(root, &path, options, excludes, hasher)?;
+            }
+        }
+        Ok(())
+    }
+
+    let excludes = build_excludes(root, options)?;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    walk(root, root, options, &excludes, &mut hasher)?;
+    Ok(hasher.finish())
+}
+
+/// Resolves a `--message` argument, reading stdin for `-` like `git commit -F -`
+fn read_message(message: &str) -> Result<String> {
+    let message = if message == "-" {
+        let mut buffer = String::new();
+        std::io::stdin()
+            .read_to_string(&mut buffer)
+            .context("Failed to read message from stdin")?;
+        buffer
+    } else {
+        message.to_string()
+    };
+
+    let message = message.trim_end();
+    if message.trim().is_empty() {
+        return Err(anyhow::anyhow!("Checkpoint message is empty"));
+    }
+    Ok(message.to_string())
+}
+
+/// Reduces a user-supplied name to the single path component used on disk
+fn normalize_checkpoint_name(name: &str) -> String {
+    Path::new(name)
+        .file_name()
+        .and_then(|f| f.to_str())
+        .unwrap_or(name)
+        .to_string()
+}
+
+/// Normalizes a name given on the command line and rejects ones that would
+/// be awkward on disk. Tabs and other control characters are refused because
+/// they would break the tab-separated `list --porcelain` output.
+fn validate_checkpoint_name(name: &str) -> Result<String> {
+    let normalized = normalize_checkpoint_name(name);
+    let trimmed = normalized.trim();
+    if trimmed.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Checkpoint name '{}' is empty or only whitespace",
+            name
+        ));
+    }
+    if trimmed.contains(char::is_control) {
+        return Err(anyhow::anyhow!(
+            "Checkpoint name {:?} contains tabs or other control characters",
+            name
+        ));
+    }
+    Ok(trimmed.to_string())
+}
+
+/// The form under which two names would refer to the same directory on
+/// normalization- or case-insensitive filesystems (e.g. APFS, NTFS)
+fn collision_key(name: &str) -> String {
+    normalize_checkpoint_name(name)
+        .nfc()
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// What the repository keeps in `.alts` under `key`, a `collision_key`,
+/// if a checkpoint of that name would clash with it
+fn reserved_name(key: &str) -> Option<&'static str> {
+    let config_file = collision_key(CONFIG_FILE);
+    if key == collision_key(OBJECTS_DIR) {
+        Some("the object store")
+    } else if key == config_file
+        || key == format!("{}.bak", config_file)
+        || key.starts_with(&format!("{}.tmp-", config_file))
+    {
+        Some("the index")
+    } else if key == collision_key(HISTORY_FILE) {
+        Some("the history log")
+    } else if key.ends_with(&collision_key(CHECKSUMS_SUFFIX)) {
+        Some("checksum manifests")
+    } else if key.starts_with('.') {
+        Some("work in progress")
+    } else {
+        None
+    }
+}
+
+fn check_name_collision(config: &Config, name: &str) -> Result<()> {
+    let key = collision_key(name);
+    if let Some(owner) = reserved_name(&key) {
+        return Err(anyhow::anyhow!(
+            "Checkpoint name '{}' is reserved for {}",
+            name,
+            owner
+        ));
+    }
+    if let Some(existing) = config
+        .checkpoints
+        .keys()
+        .find(|existing| existing.as_str() != name && collision_key(existing) == key)
+    {
+        return Err(failure(
+            FailureKind::AlreadyExists,
+            format!(
+                "Checkpoint name '{}' collides with existing checkpoint '{}' once normalized",
+                name, existing
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn list(args: &ListArgs, time_zone: Option<TimeZoneArg>) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    if !args.json && !args.porcelain && !current_dir.join(&config.target_dir).exists() {
+        warn_target_missing(&config);
+    }
+
+    if config.checkpoints.is_empty() {
+        if args.json {
+            println!("[]");
+        } else if !args.porcelain {
+            info!("No checkpoints found");
+        }
+        return Ok(());
+    }
+
+    // Convert to a vector to allow sorting
+    let mut checkpoints: Vec<(&String, &Checkpoint)> = config
+        .checkpoints
+        .iter()
+        .filter(|(_, checkpoint)| args.tags.iter().all(|tag| checkpoint.tags.contains(tag)))
+        .filter(|(name, checkpoint)| {
+            !args.missing || !checkpoint.storage_path(&alts_dir, name).exists()
+        })
+        .collect();
+
+    if !args.porcelain {
+        if checkpoints.is_empty() {
+            info!("No checkpoints match the filters");
+        } else {
+            info!("Checkpoints:");
+        }
+    }
+
+    if args.time_order {
+        // Sort by timestamp
+        checkpoints.sort_by(|a, b| a.1.timestamp.cmp(&b.1.timestamp));
+
+        if args.reverse {
+            checkpoints.reverse();
+        }
+    } else if args.reverse {
+        // Just reverse the default order (BTreeMap order)
+        checkpoints.reverse();
+    }
+
+    let total = checkpoints.len();
+    let mut footer = None;
+    if let Some(per_page) = args.per_page {
+        let per_page = per_page as usize;
+        let pages = total.div_ceil(per_page);
+        let page = args.page as usize;
+        checkpoints = checkpoints
+            .into_iter()
+            .skip((page - 1).saturating_mul(per_page))
+            .take(per_page)
+            .collect();
+        footer = Some(if page > pages {
+            format!(
+                "page {} is past the last page {} ({} total)",
+                page, pages, total
+            )
+        } else {
+            format!("page {}/{} ({} total)", page, pages, total)
+        });
+    }
+    // Porcelain output stays one line per checkpoint
+    let footer = footer.filter(|_| !args.porcelain);
+
+    if args.json {
+        let entries: Vec<ListEntryJson> = checkpoints
+            .into_iter()
+            .map(|(name, checkpoint)| ListEntryJson {
+                name,
+                timestamp: &checkpoint.timestamp,
+                exists: checkpoint.storage_path(&alts_dir, name).exists(),
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    if args.group_by_tag {
+        // Groups keep the sorted order; a checkpoint appears under each of its tags
+        let mut groups: BTreeMap<&str, Vec<(&String, &Checkpoint)>> = BTreeMap::new();
+        let mut untagged = Vec::new();
+        for &(name, checkpoint) in &checkpoints {
+            if checkpoint.tags.is_empty() {
+                untagged.push((name, checkpoint));
+            }
+            for tag in &checkpoint.tags {
+                groups.entry(tag).or_default().push((name, checkpoint));
+            }
+        }
+
+        let untagged = (!untagged.is_empty()).then_some(("(untagged)", untagged));
+        for (heading, entries) in groups.into_iter().chain(untagged) {
+            println!("{}:", heading);
+            for (name, checkpoint) in entries {
+                print_list_entry(&alts_dir, name, checkpoint, args, time_zone);
+            }
+        }
+    } else {
+        for (name, checkpoint) in checkpoints {
+            print_list_entry(&alts_dir, name, checkpoint, args, time_zone);
+        }
+    }
+
+    if let Some(footer) = footer {
+        println!("{}", footer);
+    }
+    Ok(())
+}
+
+fn print_list_entry(
+    alts_dir: &Path,
+    name: &str,
+    checkpoint: &Checkpoint,
+    args: &ListArgs,
+    time_zone: Option<TimeZoneArg>,
+) {
+    let checkpoint_path = checkpoint.storage_path(alts_dir, name);
+    let exists = checkpoint_path.exists();
+    if args.porcelain {
+        print_porcelain_entry(name, checkpoint, exists);
+        return;
+    }
+
+    let status = if exists { "✓" } else { "✗" };
+    let timestamp = display_timestamp(&checkpoint.timestamp, time_zone);
+    let name = if checkpoint.partial {
+        format!("{} (partial)", name)
+    } else {
+        name.to_string()
+    };
+    let message = checkpoint
+        .message
+        .as_deref()
+        .map(|m| format!(" - {}", truncate_message(m, LIST_MESSAGE_WIDTH)))
+        .unwrap_or_default();
+    match args.unit {
+        Some(unit) => {
+            let size = match checkpoint
+                .stored_size
+                .map_or_else(|| get_dir_size(&checkpoint_path), Ok)
+            {
+                Ok(size) if exists => format_size(size, unit),
+                Ok(_) => "N/A".to_string(),
+                Err(_) => "Unknown".to_string(),
+            };
+            println!(
+                "  {} {} - {} - {}{}",
+                status, name, timestamp, size, message
+            );
+        }
+        None => println!("  {} {} - {}{}", status, name, timestamp, message),
+    }
+}
+
+/// First line of `message`, cut to `width` characters with an ellipsis
+fn truncate_message(message: &str, width: usize) -> String {
+    let first_line = message.lines().next().unwrap_or_default();
+    let more_lines = first_line.len() < message.trim_end().len();
+    if first_line.chars().count() <= width && !more_lines {
+        return first_line.to_string();
+    }
+    let kept: String = first_line.chars().take(width.saturating_sub(1)).collect();
+    format!("{}…", kept.trim_end())
+}
+
+/// Prints the free space on the volume holding `.alts`, warning when it is
+/// below `min_free_space` or too small for another checkpoint of the target
+fn report_free_space(
+    config: &Config,
+    current_dir: &Path,
+    alts_dir: &Path,
+    unit: SizeUnit,
+) -> Result<()> {
+    let free = match fs4::available_space(alts_dir) {
+        Ok(free) => free,
+        Err(e) => {
+            println!("Free Space: Unknown ({})", e);
+            return Ok(());
+        }
+    };
+    println!("Free Space: {}", format_size(free, unit));
+
+    if let Some(threshold) = &config.min_free_space {
+        let threshold_bytes = parse_size(threshold)
+            .with_context(|| format!("Invalid min_free_space '{}'", threshold))?;
+        if free < threshold_bytes {
+            warn!(
+                "Free space {} is below the configured minimum of {}",
+                format_size(free, unit),
+                threshold
+            );
+        }
+    }
+
+    let target_path = current_dir.join(&config.target_dir);
+    if let Ok(target) = tree_stats(&target_path)
+        && free < target.total_bytes
+    {
+        warn!(
+            "Free space {} is less than the target directory's size {}; the next checkpoint will not fit",
+            format_size(free, unit),
+            format_size(target.total_bytes, unit)
+        );
+    }
+
+    Ok(())
+}
+
+/// JSON Schema of a value as `--json` serializes it
+trait JsonSchema {
+    fn json_schema() -> serde_json::Value;
+}
+
+impl JsonSchema for &str {
+    fn json_schema() -> serde_json::Value {
+        serde_json::json!({ "type": "string" })
+    }
+}
+
+impl JsonSchema for bool {
+    fn json_schema() -> serde_json::Value {
+        serde_json::json!({ "type": "boolean" })
+    }
+}
+
+impl JsonSchema for u64 {
+    fn json_schema() -> serde_json::Value {
+        serde_json::json!({ "type": "integer", "minimum": 0 })
+    }
+}
+
+impl JsonSchema for usize {
+    fn json_schema() -> serde_json::Value {
+        u64::json_schema()
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn json_schema() -> serde_json::Value {
+        serde_json::json!({ "anyOf": [T::json_schema(), { "type": "null" }] })
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema() -> serde_json::Value {
+        serde_json::json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+/// Declares a `--json` output struct together with its `JsonSchema`, so the
+/// schema is generated from the same field list serde serializes; field doc
+/// comments become descriptions
+macro_rules! json_output {
+    (
+        struct $name:ident<$lt:lifetime> {
+            $( $(#[doc = $doc:literal])* $field:ident: $ty:ty, )*
+        }
+    ) => {
+        #[derive(Serialize)]
+        struct $name<$lt> {
+            $( $(#[doc = $doc])* $field: $ty, )*
+        }
+
+        impl<$lt> JsonSchema for $name<$lt> {
+            fn json_schema() -> serde_json::Value {
+                let mut properties = serde_json::Map::new();
+                $(
+                    let mut schema = <$ty as JsonSchema>::json_schema();
+                    let description = [$($doc),*].map(str::trim).join(" ");
+                    if !description.is_empty() {
+                        schema["description"] = description.into();
+                    }
+                    properties.insert(stringify!($field).to_string(), schema);
+                )*
+                serde_json::json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": [$(stringify!($field)),*],
+                    "additionalProperties": false,
+                })
+            }
+        }
+    };
+}
+
+json_output! {
+    struct ListEntryJson<'a> {
+        name: &'a str,
+        timestamp: &'a str,
+        exists: bool,
+    }
+}
+
+json_output! {
+    struct InfoJson<'a> {
+        target_dir: &'a str,
+        target_missing: bool,
+        total_checkpoints: usize,
+        valid_checkpoints: usize,
+        invalid_checkpoints: usize,
+        /// Bytes; `null` when it cannot be determined
+        free_space: Option<u64>,
+        total_size: u64,
+        checkpoints: Vec<InfoCheckpointJson<'a>>,
+    }
+}
+
+json_output! {
+    struct InfoCheckpointJson<'a> {
+        name: &'a str,
+        timestamp: &'a str,
+        exists: bool,
+        /// Bytes; `null` when the checkpoint is missing or unreadable
+        size: Option<u64>,
+        message: Option<&'a str>,
+        created_by_version: Option<&'a str>,
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum SchemaOutput {
+    /// `alts list --json`
+    List,
+    /// `alts info --json`
+    Info,
+}
+
+/// Prints the JSON Schema of a `--json` output
+fn print_schema(output: SchemaOutput) -> Result<()> {
+    let (title, mut schema) = match output {
+        SchemaOutput::List => ("alts list --json", Vec::<ListEntryJson>::json_schema()),
+        SchemaOutput::Info => ("alts info --json", InfoJson::json_schema()),
+    };
+    schema["$schema"] = "https://json-schema.org/draft/2020-12/schema".into();
+    schema["title"] = title.into();
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+/// The `info` report as one JSON object on stdout
+fn info_json() -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    let mut sizes = BTreeMap::new();
+    fill_checkpoint_sizes(&config, &alts_dir, &mut sizes);
+    let checkpoints: Vec<InfoCheckpointJson> = config
+        .checkpoints
+        .iter()
+        .map(|(name, checkpoint)| {
+            let exists = checkpoint.storage_path(&alts_dir, name).exists();
+            InfoCheckpointJson {
+                name,
+                timestamp: &checkpoint.timestamp,
+                exists,
+                size: sizes.get(name).copied(),
+                message: checkpoint.message.as_deref(),
+                created_by_version: checkpoint.created_by_version.as_deref(),
+            }
+        })
+        .collect();
+    let valid_checkpoints = checkpoints.iter().filter(|c| c.exists).count();
+    let report = InfoJson {
+        target_dir: &config.target_dir,
+        target_missing: !current_dir.join(&config.target_dir).exists(),
+        total_checkpoints: checkpoints.len(),
+        valid_checkpoints,
+        invalid_checkpoints: checkpoints.len() - valid_checkpoints,
+        free_space: fs4::available_space(&alts_dir).ok(),
+        total_size: checkpoints.iter().filter_map(|c| c.size).sum(),
+        checkpoints,
+    };
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Prints one checkpoint in the porcelain format, see `porcelain_line`
+fn print_porcelain_entry(name: &str, checkpoint: &Checkpoint, exists: bool) {
+    println!("{}", porcelain_line(name, checkpoint, exists));
+}
+
+/// One checkpoint as `<state>\t<name>\t<timestamp>\t<tags>\t<message>`,
+/// where state is `ok` or `missing` and tags are comma-separated. This format
+/// is part of the CLI contract; add new fields only at the end of the line.
+fn porcelain_line(name: &str, checkpoint: &Checkpoint, exists: bool) -> String {
+    let state = if exists { "ok" } else { "missing" };
+    let tags: Vec<String> = checkpoint
+        .tags
+        .iter()
+        .map(|tag| porcelain_field(tag).replace(',', "\\,"))
+        .collect();
+    format!(
+        "{}\t{}\t{}\t{}\t{}",
+        state,
+        name,
+        checkpoint.timestamp,
+        tags.join(","),
+        porcelain_field(checkpoint.message.as_deref().unwrap_or_default())
+    )
+}
+
+/// One `status`/`diff` change in the porcelain format,
+/// `<change>\t<path>`
+fn porcelain_change_line(label: &str, path: &Path) -> String {
+    format!("{}\t{}", label, porcelain_field(&path.to_string_lossy()))
+}
+
+/// Escapes backslashes, tabs and line breaks so a field stays on its column
+fn porcelain_field(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Redraws the info report every `interval` seconds. Checkpoints never change
+/// once created, so their sizes are measured only once across redraws.
+fn info_watch(unit: SizeUnit, time_zone: Option<TimeZoneArg>, interval: u64) -> Result<()> {
+    let mut sizes = BTreeMap::new();
+    loop {
+        // Clear the screen and home the cursor; redrawing from scratch also
+        // adapts to a resized terminal
+        print!("\x1b[2J\x1b[H");
+        if let Err(e) = info(unit, time_zone, &mut sizes) {
+            error!("{}", e);
+        }
+        println!(
+            "\nRefreshed {}, every {}s; press Ctrl-C to stop",
+            Local::now().format("%H:%M:%S"),
+            interval
+        );
+        std::io::stdout().flush()?;
+        std::thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+/// Adds to `sizes` the stored size of every present checkpoint missing from
+/// it: the size recorded at creation, or else measured, in parallel
+fn fill_checkpoint_sizes(config: &Config, alts_dir: &Path, sizes: &mut BTreeMap<String, u64>) {
+    let found: Vec<(String, u64)> = config
+        .checkpoints
+        .par_iter()
+        .filter(|(name, _)| !sizes.contains_key(*name))
+        .filter_map(|(name, checkpoint)| {
+            let path = checkpoint.storage_path(alts_dir, name);
+            if !path.exists() {
+                return None;
+            }
+            let size = match checkpoint.stored_size {
+                Some(size) => size,
+                None => stored_size(&path).ok()?,
+            };
+            Some((name.clone(), size))
+        })
+        .collect();
+    sizes.extend(found);
+}
+
+/// Checkpoints outlive the target; say how to get it back rather than fail
+fn warn_target_missing(config: &Config) {
+    if config.checkpoints.is_empty() {
+        warn!("Target directory '{}' is missing", config.target_dir);
+    } else {
+        warn!(
+            "Target directory '{}' is missing; 'alts restore <name>' recreates it from a checkpoint",
+            config.target_dir
+        );
+    }
+}
+
+fn info(
+    unit: SizeUnit,
+    time_zone: Option<TimeZoneArg>,
+    sizes: &mut BTreeMap<String, u64>,
+) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    println!("Repository Information:");
+    println!("=======================");
+    let target_missing = !current_dir.join(&config.target_dir).exists();
+    println!(
+        "Target Directory: {}{}",
+        config.target_dir,
+        if target_missing { " (missing)" } else { "" }
+    );
+    println!("Total Checkpoints: {}", config.checkpoints.len());
+    report_free_space(&config, &current_dir, &alts_dir, unit)?;
+    if target_missing {
+        warn_target_missing(&config);
+    }
+
+    if config.checkpoints.is_empty() {
+        println!("\nNo checkpoints available.");
+        return Ok(());
+    }
+
+    // Count valid checkpoints and calculate total size
+    let mut valid_count = 0;
+    let mut invalid_count = 0;
+    let mut total_size = 0u64;
+
+    fill_checkpoint_sizes(&config, &alts_dir, sizes);
+    for (name, checkpoint) in &config.checkpoints {
+        let checkpoint_path = checkpoint.storage_path(&alts_dir, name);
+        if checkpoint_path.exists() {
+            valid_count += 1;
+            total_size += sizes.get(name).copied().unwrap_or_default();
+        } else {
+            invalid_count += 1;
+        }
+    }
+
+    println!("Valid Checkpoints: {}", valid_count);
+    println!("Invalid Checkpoints: {}", invalid_count);
+    println!("Total Size: {}", format_size(total_size, unit));
+    let objects_dir = alts_dir.join(OBJECTS_DIR);
+    if objects_dir.exists() {
+        println!(
+            "Object Store: {}",
+            format_size(get_dir_size(&objects_dir)?, unit)
+        );
+    }
+
+    println!("\nCheckpoint Details:");
+    for (name, checkpoint) in &config.checkpoints {
+        let checkpoint_path = checkpoint.storage_path(&alts_dir, name);
+        let exists = checkpoint_path.exists();
+        let status = match (exists, checkpoint.partial) {
+            (true, false) => "Valid".to_string(),
+            (true, true) => match skipped_entries(&config, &alts_dir, name) {
+                Ok(skipped) => format!(
+                    "Valid (partial: {} item(s) skipped during creation)",
+                    skipped.len()
+                ),
+                Err(_) => "Valid (partial: some items could not be copied)".to_string(),
+            },
+            (false, _) => "Missing".to_string(),
+        };
+        let size = if exists {
+            match sizes.get(name) {
+                Some(&size) => format_size(size, unit),
+                None => "Unknown".to_string(),
+            }
+        } else {
+            "N/A".to_string()
+        };
+        println!("  - Name: {}", name);
+        println!("    Status: {}", status);
+        println!("    Size: {}", size);
+        println!(
+            "    Created: {}",
+            display_timestamp(&checkpoint.timestamp, time_zone)
+        );
+        if let Some(message) = &checkpoint.message {
+            // Continuation lines line up under the first one
+            println!("    Message: {}", message.replace('\n', "\n             "));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reports the age of the newest checkpoint and warns when it exceeds
+/// `--max-age-warn`; with `--strict` that is an error instead
+fn check_checkpoint_age(args: &AgeCheckArgs) -> Result<()> {
+    let Some(max_age) = args.max_age_warn else {
+        return Ok(());
+    };
+    let config = load_config()?;
+
+    let newest = config
+        .checkpoints
+        .iter()
+        .filter_map(|(name, checkpoint)| {
+            let created = DateTime::parse_from_rfc3339(&checkpoint.timestamp).ok()?;
+            Some((created.with_timezone(&Utc), name, checkpoint))
+        })
+        .max_by_key(|(created, _, _)| *created);
+    let stale = match newest {
+        Some((created, name, checkpoint)) => {
+            info!(
+                "Latest checkpoint: '{}', created {}",
+                name,
+                format_relative_time(&checkpoint.timestamp)
+            );
+            let age = Utc::now().signed_duration_since(created);
+            age.to_std().is_ok_and(|age| age > max_age)
+        }
+        None => {
+            info!("Latest checkpoint: none");
+            true
+        }
+    };
+
+    if !stale {
+        return Ok(());
+    }
+    let message = format!(
+        "No checkpoint in the last {}; consider running 'alts checkpoint'",
+        format_duration(max_age.as_secs() as i64)
+    );
+    if args.strict {
+        return Err(anyhow::anyhow!(message));
+    }
+    warn!("{}", message);
+    Ok(())
+}
+
+/// Maps an alias or a relative reference to the checkpoint it points at;
+/// real checkpoint names win, then aliases
+fn resolve_checkpoint_name<'a>(config: &'a Config, name: &'a str) -> &'a str {
+    if config.checkpoints.contains_key(name) {
+        return name;
+    }
+    config
+        .aliases
+        .get(name)
+        .map(String::as_str)
+        .or_else(|| relative_checkpoint(config, name))
+        .unwrap_or(name)
+}
+
+/// Resolves `latest` or `@latest` to the newest checkpoint by timestamp and
+/// `@-N` to the one N steps before it, so `@-1` is the second-newest
+fn relative_checkpoint<'a>(config: &'a Config, name: &str) -> Option<&'a str> {
+    let back = match name {
+        "latest" | "@latest" => 0,
+        _ => name.strip_prefix("@-")?.parse::<usize>().ok()?,
+    };
+    newest_first(config)
+        .into_iter()
+        .nth(back)
+        .map(|(name, _)| name.as_str())
+}
+
+/// All checkpoints sorted by timestamp, newest first; unparseable timestamps
+/// count as oldest and ties go by name
+fn newest_first(config: &Config) -> Vec<(&String, &Checkpoint)> {
+    let mut dated: Vec<_> = config
+        .checkpoints
+        .iter()
+        .map(|(name, checkpoint)| {
+            (
+                DateTime::parse_from_rfc3339(&checkpoint.timestamp).ok(),
+                name,
+                checkpoint,
+            )
+        })
+        .collect();
+    dated.sort_by(|a, b| (b.0, b.1).cmp(&(a.0, a.1)));
+    dated
+        .into_iter()
+        .map(|(_, name, checkpoint)| (name, checkpoint))
+        .collect()
+}
+
+fn alias_set(label: &str, target: &str) -> Result<()> {
+    let mut config = load_config()?;
+
+    if config.checkpoints.contains_key(label) {
+        return Err(anyhow::anyhow!(
+            "'{}' is already a checkpoint name and cannot be used as an alias",
+            label
+        ));
+    }
+    let target = resolve_checkpoint_name(&config, target).to_string();
+    if !config.checkpoints.contains_key(&target) {
+        return Err(failure(
+            FailureKind::NotFound,
+            format!("Checkpoint '{}' not found in index", target),
+        ));
+    }
+
+    match config.aliases.insert(label.to_string(), target.clone()) {
+        Some(previous) => info!(
+            "Alias '{}' moved from '{}' to '{}'",
+            label, previous, target
+        ),
+        None => info!("Alias '{}' now points to '{}'", label, target),
+    }
+    save_config(&config)
+}
+
+fn alias_rm(label: &str) -> Result<()> {
+    let mut config = load_config()?;
+    if config.aliases.remove(label).is_none() {
+        return Err(failure(
+            FailureKind::NotFound,
+            format!("Alias '{}' does not exist", label),
+        ));
+    }
+    save_config(&config)?;
+    info!("Removed alias '{}'", label);
+    Ok(())
+}
+
+fn alias_list() -> Result<()> {
+    let config = load_config()?;
+    if config.aliases.is_empty() {
+        info!("No aliases defined");
+        return Ok(());
+    }
+    for (label, target) in &config.aliases {
+        println!("  {} -> {}", label, target);
+    }
+    Ok(())
+}
+
+/// Settings `alts config set` and `unset` accept
+const CONFIG_KEYS: &[&str] = &[
+    "timestamp_format",
+    "name_template",
+    "min_free_space",
+    "hash_prefix_len",
+    "exclude_always",
+    "max_file_size",
+    "confirm_if_deleting_more_than",
+    "confirm_if_reclaiming_more_than",
+    "webhook.url",
+    "webhook.authorization",
+    "compression",
+    "dedup",
+    "max_checkpoints",
+    "max_age_days",
+    "incremental",
+];
+
+/// The config without the index and aliases, which have their own commands
+fn config_settings(config: &Config) -> Result<toml::Table> {
+    let mut table = toml::Table::try_from(config).context("Failed to serialize config")?;
+    table.remove("checkpoints");
+    table.remove("aliases");
+    Ok(table)
+}
+
+fn config_get(key: &str) -> Result<()> {
+    let settings = config_settings(&load_config()?)?;
+    let mut value = None;
+    let mut table = Some(&settings);
+    for part in key.split('.') {
+        value = table.and_then(|t| t.get(part));
+        table = value.and_then(|v| v.as_table());
+    }
+    match value {
+        Some(toml::Value::String(s)) => println!("{}", s),
+        Some(toml::Value::Array(items)) => {
+            for item in items {
+                match item {
+                    toml::Value::String(s) => println!("{}", s),
+                    other => println!("{}", other),
+                }
+            }
+        }
+        Some(other) => println!("{}", other),
+        None if CONFIG_KEYS.contains(&key) => info!("'{}' is not set", key),
+        None => return Err(anyhow::anyhow!("Unknown setting '{}'", key)),
+    }
+    Ok(())
+}
+
+fn config_list() -> Result<()> {
+    let settings = config_settings(&load_config()?)?;
+    print!(
+        "{}",
+        toml::to_string_pretty(&settings).context("Failed to serialize config")?
+    );
+    Ok(())
+}
+
+fn config_set(key: &str, values: &[String]) -> Result<()> {
+    let mut config = load_config()?;
+    let value = match values {
+        [value] => value.as_str(),
+        _ if key == "exclude_always" => "",
+        _ => return Err(anyhow::anyhow!("'{}' takes exactly one value", key)),
+    };
+    let invalid = || format!("Invalid value '{}' for '{}'", value, key);
+
+    match key {
+        "timestamp_format" => {
+            validate_timestamp_format(value)?;
+            config.timestamp_format = Some(value.to_string());
+        }
+        "name_template" => {
+            let timestamp_format = config
+                .timestamp_format
+                .as_deref()
+                .unwrap_or(DEFAULT_TIMESTAMP_FORMAT);
+            render_name_template(value, "target", Utc::now(), timestamp_format, |_| false)?;
+            config.name_template = Some(value.to_string());
+        }
+        "min_free_space" => {
+            parse_size(value).with_context(invalid)?;
+            config.min_free_space = Some(value.to_string());
+        }
+        "max_file_size" => {
+            parse_size(value).with_context(invalid)?;
+            config.max_file_size = Some(value.to_string());
+        }
+        "hash_prefix_len" => {
+            let len: usize = value.parse().with_context(invalid)?;
+            if !(4..=64).contains(&len) {
+                return Err(anyhow::anyhow!(
+                    "hash_prefix_len must be between 4 and 64, got {}",
+                    len
+                ));
+            }
+            config.hash_prefix_len = Some(len);
+        }
+        "exclude_always" => config.exclude_always = values.to_vec(),
+        "confirm_if_deleting_more_than" => {
+            config.confirm_if_deleting_more_than = Some(value.parse().with_context(invalid)?);
+        }
+        "confirm_if_reclaiming_more_than" => {
+            parse_size(value).with_context(invalid)?;
+            config.confirm_if_reclaiming_more_than = Some(value.to_string());
+        }
+        "webhook.url" => {
+            if !value.starts_with("http://") && !value.starts_with("https://") {
+                return Err(anyhow::anyhow!(
+                    "webhook.url must start with http:// or https://"
+                ));
+            }
+            match &mut config.webhook {
+                Some(webhook) => webhook.url = value.to_string(),
+                None => {
+                    config.webhook = Some(Webhook {
+                        url: value.to_string(),
+                        authorization: None,
+                    })
+                }
+            }
+        }
+        "webhook.authorization" => match &mut config.webhook {
+            Some(webhook) => webhook.authorization = Some(value.to_string()),
+            None => return Err(anyhow::anyhow!("Set webhook.url first")),
+        },
+        "compression" => {
+            config.compression = match value {
+                "tar.gz" => Some(Storage::TarGz),
+                "none" => None,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "compression must be \"tar.gz\" or \"none\", got '{}'",
+                        value
+                    ));
+                }
+            };
+        }
+        "dedup" => config.dedup = value.parse().with_context(invalid)?,
+        "max_checkpoints" => config.max_checkpoints = Some(value.parse().with_context(invalid)?),
+        "max_age_days" => config.max_age_days = Some(value.parse().with_context(invalid)?),
+        "incremental" => config.incremental = value.parse().with_context(invalid)?,
+        _ => return Err(unknown_config_key(key)),
+    }
+
+    configured_storage(&config)?;
+    save_config(&config)?;
+    info!("Set '{}'", key);
+    Ok(())
+}
+
+fn config_unset(key: &str) -> Result<()> {
+    let mut config = load_config()?;
+    match key {
+        "timestamp_format" => config.timestamp_format = None,
+        "name_template" => config.name_template = None,
+        "min_free_space" => config.min_free_space = None,
+        "hash_prefix_len" => config.hash_prefix_len = None,
+        "exclude_always" => config.exclude_always.clear(),
+        "max_file_size" => config.max_file_size = None,
+        "confirm_if_deleting_more_than" => config.confirm_if_deleting_more_than = None,
+        "confirm_if_reclaiming_more_than" => config.confirm_if_reclaiming_more_than = None,
+        // A webhook cannot exist without its URL
+        "webhook.url" => config.webhook = None,
+        "webhook.authorization" => {
+            if let Some(webhook) = &mut config.webhook {
+                webhook.authorization = None;
+            }
+        }
+        "compression" => config.compression = None,
+        "dedup" => config.dedup = false,
+        "max_checkpoints" => config.max_checkpoints = None,
+        "max_age_days" => config.max_age_days = None,
+        "incremental" => config.incremental = false,
+        _ => return Err(unknown_config_key(key)),
+    }
+    save_config(&config)?;
+    info!("Unset '{}'", key);
+    Ok(())
+}
+
+fn unknown_config_key(key: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Unknown or read-only setting '{}'; settable keys: {}",
+        key,
+        CONFIG_KEYS.join(", ")
+    )
+}
+
+/// Looks up an indexed checkpoint whose directory is present on disk
+fn find_checkpoint<'a>(
+    config: &'a Config,
+    alts_dir: &Path,
+    name: &str,
+) -> Result<(&'a Checkpoint, PathBuf)> {
+    let checkpoint = config.checkpoints.get(name).ok_or_else(|| {
+        failure(
+            FailureKind::NotFound,
+            format!("Checkpoint '{}' not found in index", name),
+        )
+    })?;
+
+    let checkpoint_path = checkpoint.storage_path(alts_dir, name);
+    if !checkpoint_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Checkpoint '{}' is in the index but its data is missing",
+            name
+        ));
+    }
+
+    Ok((checkpoint, checkpoint_path))
+}
+
+fn restore(
+    name: &str,
+    only_changed: bool,
+    verify_after: bool,
+    to_checkpoint_time: bool,
+    into: Option<&Path>,
+    force: bool,
+) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+    let name = resolve_checkpoint_name(&config, name);
+    let (checkpoint, _) = find_checkpoint(&config, &alts_dir, name)?;
+    let (target_path, target_label) = match into {
+        Some(into) => (into.to_path_buf(), into.display().to_string()),
+        None => (
+            current_dir.join(checkpoint.source(&config)),
+            checkpoint.source(&config).to_string(),
+        ),
+    };
+    if into.is_some() && target_path.exists() {
+        let is_empty = fs::read_dir(&target_path)
+            .with_context(|| format!("'{}' is not a readable directory", target_label))?
+            .next()
+            .is_none();
+        if !is_empty && !force {
+            return Err(anyhow::anyhow!(
+                "'{}' is not empty; use --force to write into it anyway",
+                target_label
+            ));
+        }
+    }
+
+    // Parse up front so a bad index entry fails before the target is touched
+    let mtime = if to_checkpoint_time {
+        let created = DateTime::parse_from_rfc3339(&checkpoint.timestamp).with_context(|| {
+            format!(
+                "Checkpoint '{}' has an unparseable timestamp '{}'",
+                name, checkpoint.timestamp
+            )
+        })?;
+        Some(SystemTime::from(created))
+    } else {
+        None
+    };
+
+    info!("Restoring checkpoint '{}' into '{}'...", name, target_label);
+
+    with_materialized(&config, &alts_dir, name, |checkpoint_path| {
+        let started = Instant::now();
+        if only_changed {
+            let mut stats = SyncStats::default();
+            sync_dir(checkpoint_path, &target_path, true, &mut stats)?;
+            info!(
+                "Checkpoint '{}' restored: {} file(s) written, {} unchanged, {} removed, {}",
+                name,
+                stats.written,
+                stats.skipped,
+                stats.removed,
+                format_transfer(stats.bytes, started.elapsed())
+            );
+        } else {
+            // Only the target is ours to wipe; other directories are merged into
+            if into.is_none() && target_path.exists() {
+                clear_dir(&target_path)?;
+            }
+            let options = CopyOptions {
+                exclude_patterns: vec![format!("/{}", ERRORS_FILE)],
+                ..Default::default()
+            };
+            let copied = copy_dir_recursive(checkpoint_path, &target_path, &options)?;
+            info!(
+                "Checkpoint '{}' restored successfully: {} file(s), {}",
+                name,
+                copied.files,
+                format_transfer(copied.bytes, started.elapsed())
+            );
+        }
+
+        if let Some(mtime) = mtime {
+            let touched = set_file_mtimes(&target_path, mtime)?;
+            info!(
+                "Set mtime of {} file(s) to {}",
+                touched, checkpoint.timestamp
+            );
+        }
+
+        if into.is_none() {
+            record_event(&alts_dir, "restore", &[name]);
+        }
+
+        if verify_after {
+            info!("Verifying restored tree...");
+            let changes = diff_trees(checkpoint_path, &target_path)?;
+            if !changes.is_empty() {
+                for change in &changes {
+                    let problem = match change.kind {
+                        ChangeKind::Added => "unexpected in target",
+                        ChangeKind::Removed => "missing from target",
+                        ChangeKind::Modified => "differs from checkpoint",
+                    };
+                    error!("  {}: {}", change.path.display(), problem);
+                }
+                return Err(failure(
+                    FailureKind::VerificationFailed,
+                    format!(
+                        "Verification failed: {} discrepancy(ies) between checkpoint '{}' and '{}'",
+                        changes.len(),
+                        name,
+                        target_label
+                    ),
+                ));
+            }
+            info!("Verification passed: target matches checkpoint '{}'", name);
+        }
+
+        Ok(())
+    })
+}
+
+/// Dry run of a full restore: lists every file it would touch without
+/// changing anything
+fn impact(name: &str, unit: SizeUnit) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    let name = resolve_checkpoint_name(&config, name);
+    let (checkpoint, _) = find_checkpoint(&config, &alts_dir, name)?;
+    let target_path = current_dir.join(checkpoint.source(&config));
+
+    let mut counts = [0u64; 3];
+    let mut bytes = 0;
+    with_materialized(&config, &alts_dir, name, |checkpoint_path| {
+        for change in diff_trees(&target_path, checkpoint_path)? {
+            // Restoring makes the target look like the checkpoint
+            let (action, source, index) = match change.kind {
+                ChangeKind::Added => ("create", checkpoint_path, 0),
+                ChangeKind::Modified => ("overwrite", checkpoint_path, 1),
+                ChangeKind::Removed => ("delete", target_path.as_path(), 2),
+            };
+            let metadata = fs::symlink_metadata(source.join(&change.path))?;
+            // Directories appear through the files inside them
+            if metadata.is_dir() && change.kind != ChangeKind::Modified {
+                continue;
+            }
+            println!("  {:<9} {}", action, change.path.display());
+            counts[index] += 1;
+            if metadata.is_file() {
+                bytes += metadata.len();
+            }
+        }
+        Ok(())
+    })?;
+
+    let [created, overwritten, deleted] = counts;
+    if created + overwritten + deleted == 0 {
+        info!(
+            "'{}' already matches checkpoint '{}'",
+            config.target_dir, name
+        );
+        return Ok(());
+    }
+    println!(
+        "Restoring '{}' would create {}, overwrite {} and delete {} file(s), touching {}",
+        name,
+        created,
+        overwritten,
+        deleted,
+        format_size(bytes, unit)
+    );
+    Ok(())
+}
+
+/// Compares a checkpoint against another checkpoint, an arbitrary directory
+/// (e.g. a copy received from someone else) or, by default, the target
+fn diff(name: &str, other: Option<&str>, external: Option<&Path>, porcelain: bool) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+    let name = resolve_checkpoint_name(&config, name);
+
+    if let Some(other) = other {
+        let other = resolve_checkpoint_name(&config, other);
+        let differences = with_materialized(&config, &alts_dir, name, |old| {
+            with_materialized(&config, &alts_dir, other, |new| {
+                print_tree_changes(old, new, &diff_trees(old, new)?, porcelain)
+            })
+        })?;
+        if !porcelain {
+            report_differences(differences, &format!("checkpoint '{}'", other), name);
+        }
+        return Ok(());
+    }
+
+    let (compared, label) = match external {
+        Some(external) => (external.to_path_buf(), external.display().to_string()),
+        None => {
+            let (checkpoint, _) = find_checkpoint(&config, &alts_dir, name)?;
+            let source = checkpoint.source(&config);
+            (current_dir.join(source), source.to_string())
+        }
+    };
+    if !compared.exists() {
+        return Err(anyhow::anyhow!("'{}' does not exist", label));
+    }
+    if !compared.is_dir() {
+        return Err(anyhow::anyhow!("'{}' is not a directory", label));
+    }
+
+    let differences = with_materialized(&config, &alts_dir, name, |checkpoint_path| {
+        let changes = diff_trees(checkpoint_path, &compared)?;
+        print_tree_changes(checkpoint_path, &compared, &changes, porcelain)
+    })?;
+    if !porcelain {
+        report_differences(differences, &format!("'{}'", label), name);
+    }
+    Ok(())
+}
+
+/// Compares the target with the newest checkpoint, leaving out paths a new
+/// checkpoint would exclude anyway
+fn status(porcelain: bool) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+    let target_path = current_dir.join(&config.target_dir);
+
+    let Some(latest) = config
+        .checkpoints
+        .iter()
+        .filter(|(_, c)| c.source_dir.is_none())
+        .filter_map(|(name, c)| Some((DateTime::parse_from_rfc3339(&c.timestamp).ok()?, name)))
+        .max()
+        .map(|(_, name)| name)
+    else {
+        info!(
+            "No checkpoints yet; everything in '{}' is new",
+            config.target_dir
+        );
+        return Ok(());
+    };
+
+    let options = target_copy_options(&config, &target_path)?;
+    let excludes = build_excludes(&target_path, &options)?;
+    let max_file_size = options.max_file_size;
+    let changes = with_materialized(&config, &alts_dir, latest, |checkpoint_path| {
+        let mut changes = diff_trees(checkpoint_path, &target_path)?;
+        changes.retain(|change| {
+            let is_dir = target_path.join(&change.path).is_dir();
+            // The next checkpoint would leave an oversized new file out too
+            let oversized = change.kind == ChangeKind::Added
+                && max_file_size.is_some_and(|max| {
+                    fs::metadata(target_path.join(&change.path)).is_ok_and(|m| m.len() > max)
+                });
+            !change.path.iter().any(|part| part == ALTS_DIR)
+                && !oversized
+                && !excludes
+                    .matched_path_or_any_parents(&change.path, is_dir)
+                    .is_ignore()
+        });
+        print_tree_changes(checkpoint_path, &target_path, &changes, porcelain)
+    })?;
+
+    if porcelain {
+        return Ok(());
+    }
+    if changes == 0 {
+        info!("Nothing changed since checkpoint '{}'", latest);
+    } else {
+        println!("{} change(s) since checkpoint '{}'", changes, latest);
+    }
+    Ok(())
+}
+
+fn report_differences(differences: usize, compared: &str, name: &str) {
+    if differences == 0 {
+        info!("{} matches checkpoint '{}'", compared, name);
+    } else {
+        println!("{} difference(s)", differences);
+    }
+}
+
+/// Prints each change going from `old` to `new` and returns how many were
+/// printed. Added or deleted directories show up through their files.
+fn print_tree_changes(
+    old: &Path,
+    new: &Path,
+    changes: &[TreeChange],
+    porcelain: bool,
+) -> Result<usize> {
+    let mut printed = 0;
+    for change in changes {
+        let (label, side) = match change.kind {
+            ChangeKind::Added => ("added", new),
+            ChangeKind::Modified => ("modified", new),
+            ChangeKind::Removed => ("deleted", old),
+        };
+        let metadata = fs::symlink_metadata(side.join(&change.path))?;
+        if metadata.is_dir() && change.kind != ChangeKind::Modified {
+            continue;
+        }
+        if porcelain {
+            println!("{}", porcelain_change_line(label, &change.path));
+        } else {
+            println!("  {:<8} {}", label, change.path.display());
+        }
+        printed += 1;
+    }
+    Ok(printed)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+struct TreeChange {
+    /// Path relative to the compared roots
+    path: PathBuf,
+    kind: ChangeKind,
+}
+
+enum TreeEntry {
+    Dir,
+    File { size: u64 },
+    Symlink(PathBuf),
+}
+
+/// Every entry below `root`, keyed by relative path, without following
+/// symlinks. A checkpoint's `ERRORS_FILE` is not part of its tree.
+fn collect_tree(root: &Path) -> Result<BTreeMap<PathBuf, TreeEntry>> {
+    fn walk(root: &Path, dir: &Path, entries: &mut BTreeMap<PathBuf, TreeEntry>) -> Result<()> {
+        for entry in fs::read_dir(dir)
+            .with_context(|| format!("Failed to read directory '{}'", dir.display()))?
+        {
+            let entry = entry?;
+            if dir == root && entry.file_name() == ERRORS_FILE {
+                continue;
+            }
+            let path = entry.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+            let file_type = entry.file_type()?;
+
+            if file_type.is_symlink() {
+                entries.insert(relative, TreeEntry::Symlink(fs::read_link(&path)?));
+            } else if file_type.is_dir() {
+                entries.insert(relative, TreeEntry::Dir);
+                walk(root, &path, entries)?;
+            } else {
+                let size = entry.metadata()?.len();
+                entries.insert(relative, TreeEntry::File { size });
+            }
+        }
+        Ok(())
+    }
+
+    let mut entries = BTreeMap::new();
+    if root.exists() {
+        walk(root, root, &mut entries)?;
+    }
+    Ok(entries)
+}
+
+/// Lists what changed going from `old` to `new`, sorted by path. Files of
+/// equal size are compared by content hash. A missing root is treated as an
+/// empty tree.
+fn diff_trees(old: &Path, new: &Path) -> Result<Vec<TreeChange>> {
+    let old_entries = collect_tree(old)?;
+    let new_entries = collect_tree(new)?;
+    let mut changes = Vec::new();
+    // Same-size files need their content compared; hash those in parallel
+    let mut candidates = Vec::new();
+
+    for (path, old_entry) in &old_entries {
+        let kind = match (old_entry, new_entries.get(path)) {
+            (_, None) => ChangeKind::Removed,
+            (TreeEntry::Dir, Some(TreeEntry::Dir)) => continue,
+            (TreeEntry::Symlink(a), Some(TreeEntry::Symlink(b))) if a == b => continue,
+            (TreeEntry::File { size: a }, Some(TreeEntry::File { size: b })) if a == b => {
+                candidates.push(path);
+                continue;
+            }
+            _ => ChangeKind::Modified,
+        };
+        changes.push(TreeChange {
+            path: path.clone(),
+            kind,
+        });
+    }
+
+    let modified = candidates
+        .into_par_iter()
+        .map(|path| {
+            let same = hash_file(&old.join(path))? == hash_file(&new.join(path))?;
+            Ok((!same).then(|| path.clone()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    changes.extend(modified.into_iter().flatten().map(|path| TreeChange {
+        path,
+        kind: ChangeKind::Modified,
+    }));
+
+    for path in new_entries.keys() {
+        if !old_entries.contains_key(path) {
+            changes.push(TreeChange {
+                path: path.clone(),
+                kind: ChangeKind::Added,
+            });
+        }
+    }
+
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+/// Content hash of a whole tree. Files are hashed in parallel, then folded in
+/// path order so the result never depends on the number of threads.
+fn tree_hash(root: &Path) -> Result<String> {
+    let entries = collect_tree(root)?;
+    let file_hashes = entries
+        .par_iter()
+        .filter(|(_, entry)| matches!(entry, TreeEntry::File { .. }))
+        .map(|(path, _)| Ok((path, hash_file(&root.join(path))?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    let mut hasher = Sha256::new();
+    for (path, entry) in &entries {
+        let display = path.to_string_lossy();
+        match entry {
+            TreeEntry::Dir => hasher.update(format!("d\0{}\n", display)),
+            TreeEntry::File { .. } => {
+                hasher.update(format!("f\0{}\0{}\n", display, file_hashes[path]))
+            }
+            TreeEntry::Symlink(target) => {
+                hasher.update(format!("l\0{}\0{}\n", display, target.to_string_lossy()))
+            }
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Sets the modification time of every regular file below `root`, returning
+/// how many were updated. Symlinks and directories are left alone.
+fn set_file_mtimes(root: &Path, mtime: SystemTime) -> Result<u64> {
+    let mut touched = 0;
+    for (path, entry) in collect_tree(root)? {
+        if let TreeEntry::File { .. } = entry {
+            let path = root.join(path);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(mtime))
+                .with_context(|| format!("Failed to set mtime of '{}'", path.display()))?;
+            touched += 1;
+        }
+    }
+    Ok(touched)
+}
+
+/// Removes everything inside `dir`, leaving the directory itself in place
+fn clear_dir(dir: &Path) -> Result<()> {
+    for entry in fs::read_dir(dir).context("Failed to read directory")? {
+        let entry = entry?;
+        if is_repository(&entry.path()) {
+            continue;
+        }
+        remove_path(&entry.path())?;
+    }
+    Ok(())
+}
+
+/// Whether `path` is this repository's own `.alts`, which a target that
+/// contains it must never lose to a restore
+fn is_repository(path: &Path) -> bool {
+    let Ok(alts_dir) = repo_dir().map(|dir| dir.join(ALTS_DIR)) else {
+        return false;
+    };
+    match (path.canonicalize(), alts_dir.canonicalize()) {
+        (Ok(path), Ok(alts_dir)) => path == alts_dir,
+        _ => false,
+    }
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        fs::remove_dir_all(path).or_else(|e| {
+            // Copies keep read-only directories read-only, which blocks removing their entries
+            if e.kind() != std::io::ErrorKind::PermissionDenied {
+                return Err(e);
+            }
+            make_dirs_writable(path)?;
+            fs::remove_dir_all(path)
+        })
+    } else {
+        fs::remove_file(path)
+    }
+    .with_context(|| format!("Failed to remove '{}'", path.display()))
+}
+
+/// Gives the owner write access to every directory below `root`
+#[cfg(unix)]
+fn make_dirs_writable(root: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = fs::symlink_metadata(root)?;
+    if !metadata.is_dir() {
+        return Ok(());
+    }
+    let mode = metadata.permissions().mode();
+    fs::set_permissions(root, fs::Permissions::from_mode(mode | 0o700))?;
+    for entry in fs::read_dir(root)? {
+        make_dirs_writable(&entry?.path())?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_dirs_writable(_root: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[derive(Default)]
+struct SyncStats {
+    written: u64,
+    /// Bytes of file content written
+    bytes: u64,
+    skipped: u64,
+    removed: u64,
+}
+
+/// Makes `dst` mirror `src`, only touching entries whose content differs.
+/// At the `top` of a checkpoint, its `ERRORS_FILE` is neither copied nor
+/// removed.
+fn sync_dir(src: &Path, dst: &Path, top: bool, stats: &mut SyncStats) -> Result<()> {
+    if fs::symlink_metadata(dst).is_ok_and(|m| !m.is_dir()) {
+        remove_path(dst)?;
+    }
+    fs::create_dir_all(dst).context("Failed to create directory")?;
+
+    let mut seen = std::collections::BTreeSet::new();
+    if top {
+        seen.insert(ERRORS_FILE.into());
+    }
+
+    for entry in fs::read_dir(src).context("Failed to read directory")? {
+        let entry = entry?;
+        if top && entry.file_name() == ERRORS_FILE {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let src_path = entry.path();
+        let dst_path = dst.join(entry.file_name());
+        let existing = fs::symlink_metadata(&dst_path).ok();
+        seen.insert(entry.file_name());
+
+        if file_type.is_dir() {
+            sync_dir(&src_path, &dst_path, false, stats)?;
+        } else if file_type.is_symlink() {
+            let link_target = fs::read_link(&src_path)?;
+            let unchanged = existing
+                .as_ref()
+                .is_some_and(|m| m.file_type().is_symlink())
+                && fs::read_link(&dst_path)? == link_target;
+            if unchanged {
+                stats.skipped += 1;
+                continue;
+            }
+            if existing.is_some() {
+                remove_path(&dst_path)?;
+            }
+            info!("Writing symlink: {}", dst_path.display());
+            let is_dir = src_path.is_dir();
+            create_symlink(&link_target, &dst_path, is_dir).context("Failed to create symlink")?;
+            stats.written += 1;
+        } else {
+            let unchanged = match &existing {
+                Some(m) if m.is_file() => {
+                    m.len() == entry.metadata()?.len()
+                        && hash_file(&src_path)? == hash_file(&dst_path)?
+                }
+                _ => false,
+            };
+            if unchanged {
+                stats.skipped += 1;
+                continue;
+            }
+            if existing.is_some() {
+                remove_path(&dst_path)?;
+            }
+            info!("Writing file: {}", dst_path.display());
+            stats.bytes += fs::copy(&src_path, &dst_path).context("Failed to copy file")?;
+            FileMeta::read(&src_path)?.apply(&dst_path)?;
+            stats.written += 1;
+        }
+    }
+
+    for entry in fs::read_dir(dst).context("Failed to read directory")? {
+        let entry = entry?;
+        if !seen.contains(&entry.file_name()) && !is_repository(&entry.path()) {
+            info!("Removing: {}", entry.path().display());
+            remove_path(&entry.path())?;
+            stats.removed += 1;
+        }
+    }
+
+    FileMeta::read(src)?.apply(dst)
+}
+
+/// Per-file SHA-256 hashes of a checkpoint, kept beside it in `.alts`
+#[derive(Serialize, Deserialize)]
+struct FileChecksums {
+    files: BTreeMap<String, String>,
+}
+
+fn checksums_path(alts_dir: &Path, name: &str) -> PathBuf {
+    alts_dir.join(format!("{}{}", name, CHECKSUMS_SUFFIX))
+}
+
+/// Hashes every regular file below `root` in parallel
+fn file_checksums(root: &Path) -> Result<FileChecksums> {
+    let paths: Vec<PathBuf> = collect_tree(root)?
+        .into_iter()
+        .filter(|(_, entry)| matches!(entry, TreeEntry::File { .. }))
+        .map(|(path, _)| path)
+        .collect();
+    let files = paths
+        .into_par_iter()
+        .map(|path| {
+            let hash = hash_file(&root.join(&path))?;
+            Ok((path.to_string_lossy().into_owned(), hash))
+        })
+        .collect::<Result<_>>()?;
+    Ok(FileChecksums { files })
+}
+
+/// Re-hashes checkpoints against their checksum manifests and reports
+/// mismatched, missing and extra files. Fails if any problem is found.
+fn verify(name: Option<&str>) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    let names: Vec<&str> = match name {
+        Some(name) => {
+            let name = resolve_checkpoint_name(&config, name);
+            find_checkpoint(&config, &alts_dir, name)?;
+            vec![name]
+        }
+        None => config.checkpoints.keys().map(String::as_str).collect(),
+    };
+
+    let mut verified = 0;
+    let mut problems = 0;
+    for name in names {
+        let path = checksums_path(&alts_dir, name);
+        if !path.exists() {
+            warn!("'{}': no checksum manifest, skipped", name);
+            continue;
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read '{}'", path.display()))?;
+        let expected: FileChecksums = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse '{}'", path.display()))?;
+        let actual = with_materialized(&config, &alts_dir, name, file_checksums)?;
+
+        let mut found = Vec::new();
+        for (file, hash) in &expected.files {
+            match actual.files.get(file) {
+                None => found.push(format!("missing: {}", file)),
+                Some(actual_hash) if actual_hash != hash => {
+                    found.push(format!("mismatch: {}", file))
+                }
+                Some(_) => {}
+            }
+        }
+        for file in actual.files.keys() {
+            if !expected.files.contains_key(file) {
+                found.push(format!("extra: {}", file));
+            }
+        }
+
+        if found.is_empty() {
+            info!("'{}': {} file(s) OK", name, expected.files.len());
+        } else {
+            error!("'{}': {} problem(s):", name, found.len());
+            for problem in &found {
+                error!("  {}", problem);
+            }
+            problems += found.len();
+        }
+        verified += 1;
+    }
+
+    if problems > 0 {
+        return Err(failure(
+            FailureKind::VerificationFailed,
+            format!("Verification found {} problem(s)", problems),
+        ));
+    }
+    info!("Verified {} checkpoint(s)", verified);
+    Ok(())
+}
+
+/// Hex-encoded SHA-256 digest of a file's content
+fn hash_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .with_context(|| format!("Failed to open '{}' for hashing", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to hash '{}'", path.display()))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn stat(name: &str, unit: SizeUnit, time_zone: Option<TimeZoneArg>) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    let name = resolve_checkpoint_name(&config, name);
+    let (checkpoint, checkpoint_path) = find_checkpoint(&config, &alts_dir, name)?;
+    let stats = if checkpoint.storage == Storage::Dir {
+        tree_stats(&checkpoint_path)?
+    } else {
+        with_materialized(&config, &alts_dir, name, tree_stats)?
+    };
+
+    println!("Checkpoint: {}", name);
+    println!(
+        "Created: {} ({})",
+        display_timestamp(&checkpoint.timestamp, time_zone),
+        format_relative_time(&checkpoint.timestamp)
+    );
+    if let Some(message) = &checkpoint.message {
+        println!("Message: {}", message);
+    }
+    if !checkpoint.tags.is_empty() {
+        println!("Tags: {}", checkpoint.tags.join(", "));
+    }
+    if let Some(hash) = &checkpoint.hash {
+        println!("Hash: {}", hash);
+    }
+    if let Some(base) = &checkpoint.delta_base {
+        println!("Delta Base: {}", base);
+    }
+    if let Some(version) = &checkpoint.created_by_version {
+        println!("Created By: alts {}", version);
+    }
+    if checkpoint.partial {
+        let skipped = skipped_entries(&config, &alts_dir, name)?;
+        println!("Partial: {} item(s) skipped during creation", skipped.len());
+        for line in &skipped {
+            println!("  {}", line);
+        }
+    }
+    println!("Files: {}", stats.files);
+    println!("Directories: {}", stats.dirs);
+    println!("Total Size: {}", format_size(stats.total_bytes, unit));
+    if checkpoint.storage == Storage::TarGz {
+        println!(
+            "Compressed Size: {}",
+            format_size(stored_size(&checkpoint_path)?, unit)
+        );
+    }
+    match &stats.largest {
+        Some((path, size)) => println!(
+            "Largest File: {} ({})",
+            path.display(),
+            format_size(*size, unit)
+        ),
+        None => println!("Largest File: N/A"),
+    }
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct TreeStats {
+    files: u64,
+    dirs: u64,
+    total_bytes: u64,
+    /// Path relative to the walked root, and its size in bytes
+    largest: Option<(PathBuf, u64)>,
+}
+
+fn tree_stats(root: &Path) -> Result<TreeStats> {
+    let mut stats = TreeStats::default();
+    tree_stats_inner(root, root, &mut stats)?;
+    Ok(stats)
+}
+
+/// Bytes a checkpoint's data occupies: the archive size or the tree total
+fn stored_size(path: &Path) -> Result<u64> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        Ok(tree_stats(path)?.total_bytes)
+    } else {
+        Ok(fs::metadata(path)?.len())
+    }
+}
+
+fn tree_stats_inner(root: &Path, dir: &Path, stats: &mut TreeStats) -> Result<()> {
+    for entry in fs::read_dir(dir).context("Failed to read directory")? {
+        let entry = entry?;
+        if dir == root && entry.file_name() == ERRORS_FILE {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        let path = entry.path();
+
+        if file_type.is_dir() {
+            stats.dirs += 1;
+            tree_stats_inner(root, &path, stats)?;
+        } else if file_type.is_file() {
+            let size = entry.metadata()?.len();
+            stats.files += 1;
+            stats.total_bytes += size;
+            if stats.largest.as_ref().is_none_or(|(_, s)| size > *s) {
+                let relative = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+                stats.largest = Some((relative, size));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn format_relative_time(timestamp: &str) -> String {
+    let Ok(then) = DateTime::parse_from_rfc3339(timestamp) else {
+        return "unknown".to_string();
+    };
+    let seconds = Utc::now()
+        .signed_duration_since(then.with_timezone(&Utc))
+        .num_seconds();
+
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+    if seconds < 60 {
+        return "just now".to_string();
+    }
+    format!("{} ago", format_duration(seconds))
+}
+
+/// Renders a number of seconds in its largest whole unit, e.g. "3 days"
+fn format_duration(seconds: i64) -> String {
+    const UNITS: &[(i64, &str)] = &[
+        (365 * 24 * 3600, "year"),
+        (30 * 24 * 3600, "month"),
+        (7 * 24 * 3600, "week"),
+        (24 * 3600, "day"),
+        (3600, "hour"),
+        (60, "minute"),
+    ];
+
+    for (unit_seconds, unit) in UNITS {
+        let count = seconds / unit_seconds;
+        if count > 0 {
+            let plural = if count == 1 { "" } else { "s" };
+            return format!("{} {}{}", count, unit, plural);
+        }
+    }
+
+    let plural = if seconds == 1 { "" } else { "s" };
+    format!("{} second{}", seconds, plural)
+}
+
+fn debug_size_compare(name: &str) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+    let name = resolve_checkpoint_name(&config, name);
+    let (checkpoint, checkpoint_path) = find_checkpoint(&config, &alts_dir, name)?;
+    // Only a plain tree has sizes both walkers see the same way
+    let stored_as = match checkpoint.storage {
+        Storage::Dir => None,
+        Storage::TarGz => Some("a compressed archive"),
+        Storage::Objects => Some("deduplicated objects"),
+    };
+    if let Some(stored_as) = stored_as {
+        return Err(anyhow::anyhow!(
+            "Checkpoint '{}' is stored as {}; size-compare only measures plain directory checkpoints",
+            name,
+            stored_as
+        ));
+    }
+
+    let native_bytes = tree_stats(&checkpoint_path)?.total_bytes;
+    println!("Native (apparent): {} bytes", native_bytes);
+
+    let du_kb = match du_size_kb(&checkpoint_path) {
+        Ok(kb) => kb,
+        Err(e) => {
+            println!("du: unavailable ({}), skipping comparison", e);
+            return Ok(());
+        }
+    };
+    let du_bytes = du_kb * 1024;
+    println!("du -sk (allocated): {} bytes", du_bytes);
+
+    let difference = du_bytes as i64 - native_bytes as i64;
+    println!("Difference: {} bytes", difference);
+    if difference == 0 {
+        return Ok(());
+    }
+
+    let (hardlinked, sparse) = size_anomalies(&checkpoint_path)?;
+    println!("\nPossible explanations:");
+    if difference > 0 {
+        println!(
+            "  - du counts whole allocated blocks (directories included), so small files round up"
+        );
+    }
+    if hardlinked > 0 {
+        println!(
+            "  - {} file(s) have multiple hard links; du counts them once, the walker for every path",
+            hardlinked
+        );
+    }
+    if sparse > 0 {
+        println!(
+            "  - {} sparse file(s) occupy fewer blocks than their apparent length",
+            sparse
+        );
+    }
+
+    Ok(())
+}
+
+fn rename(old: &str, new: &str) -> Result<()> {
+    let mut config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    let old = resolve_checkpoint_name(&config, old).to_string();
+    find_checkpoint(&config, &alts_dir, &old)?;
+    let new = validate_checkpoint_name(new)?;
+    if new == old {
+        info!("Checkpoint '{}' already has that name", old);
+        return Ok(());
+    }
+    if config.checkpoints.contains_key(&new) {
+        return Err(failure(
+            FailureKind::AlreadyExists,
+            format!("Checkpoint name '{}' already exists", new),
+        ));
+    }
+
+    let checkpoint = config
+        .checkpoints
+        .remove(&old)
+        .expect("found in the index above");
+    // Only a change of case or normalization may collide with the old name
+    check_name_collision(&config, &new)?;
+    let from = checkpoint.storage_path(&alts_dir, &old);
+    let to = checkpoint.storage_path(&alts_dir, &new);
+    if fs::symlink_metadata(&to).is_ok() {
+        return Err(failure(
+            FailureKind::AlreadyExists,
+            format!("'{}' already exists in {}", to.display(), ALTS_DIR),
+        ));
+    }
+    fs::rename(&from, &to).with_context(|| format!("Failed to rename checkpoint '{}'", old))?;
+    let checksums = checksums_path(&alts_dir, &old);
+    if checksums.exists() {
+        fs::rename(&checksums, checksums_path(&alts_dir, &new))
+            .context("Failed to rename checksum manifest")?;
+    }
+    config.checkpoints.insert(new.clone(), checkpoint);
+
+    for (label, target) in &mut config.aliases {
+        if *target == old {
+            info!("Alias '{}' now points to '{}'", label, new);
+            *target = new.clone();
+        }
+    }
+    for checkpoint in config.checkpoints.values_mut() {
+        if checkpoint.delta_base.as_deref() == Some(old.as_str()) {
+            checkpoint.delta_base = Some(new.clone());
+        }
+        if checkpoint.linked_from.as_deref() == Some(old.as_str()) {
+            checkpoint.linked_from = Some(new.clone());
+        }
+    }
+    save_config(&config)?;
+    record_event(&alts_dir, "rename", &[&old, &new]);
+    info!("Renamed checkpoint '{}' to '{}'", old, new);
+    Ok(())
+}
+
+/// Exports every checkpoint `select` picks to `<dir>/<name>.tar.gz`
+fn export_selected(select: &SelectArgs, dir: &Path, time_zone: Option<TimeZoneArg>) -> Result<()> {
+    let names = select_checkpoints(&load_config()?, select, time_zone)?;
+    if names.is_empty() {
+        info!("No checkpoints match");
+        return Ok(());
+    }
+    fs::create_dir_all(dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    for name in &names {
+        export(name, &dir.join(format!("{}.tar.gz", name)), None, None)?;
+    }
+    Ok(())
+}
+
+/// Index entry of an exported checkpoint, kept in the archive as
+/// `checkpoint.toml` next to the full tree under `tree/`, or only the
+/// changed entries when exported relative to a base
+#[derive(Serialize, Deserialize)]
+struct ExportedCheckpoint {
+    name: String,
+    #[serde(default)]
+    timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_by_version: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    partial: bool,
+    /// Content of the partial checkpoint's `ERRORS_FILE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    errors: Option<String>,
+    /// Paths of the base that the checkpoint no longer has
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    deleted: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    base: Option<ExportBase>,
+}
+
+/// The checkpoint a relative export only holds changes against
+#[derive(Serialize, Deserialize)]
+struct ExportBase {
+    name: String,
+    /// `tree_hash` of the base, which the checkpoint given to `--onto` must match
+    hash: String,
+}
+
+fn export(name: &str, file: &Path, relative_to: Option<&str>, split: Option<u64>) -> Result<()> {
+    let config = load_config()?;
+    let alts_dir = repo_dir()?.join(ALTS_DIR);
+    let name = resolve_checkpoint_name(&config, name);
+    let (checkpoint, _) = find_checkpoint(&config, &alts_dir, name)?;
+    let base = relative_to.map(|base| resolve_checkpoint_name(&config, base));
+    if let Some(base) = base {
+        find_checkpoint(&config, &alts_dir, base)?;
+    }
+    let mut outputs = vec![file.to_path_buf()];
+    if split.is_some() {
+        outputs.push(split_manifest_path(file));
+        outputs.push(split_part_path(file, 1));
+    }
+    if let Some(existing) = outputs
+        .iter()
+        .find(|path| fs::symlink_metadata(path).is_ok())
+    {
+        return Err(failure(
+            FailureKind::AlreadyExists,
+            format!("'{}' already exists", existing.display()),
+        ));
+    }
+
+    // Deltas and compressed or deduplicated storage are resolved, so the
+    // archive always holds the plain tree
+    let written = with_materialized(&config, &alts_dir, name, |root| {
+        let errors = if checkpoint.partial {
+            Some(
+                fs::read_to_string(root.join(ERRORS_FILE))
+                    .with_context(|| format!("Failed to read {} of '{}'", ERRORS_FILE, name))?,
+            )
+        } else {
+            None
+        };
+        let mut exported = ExportedCheckpoint {
+            name: name.to_string(),
+            timestamp: checkpoint.timestamp.clone(),
+            message: checkpoint.message.clone(),
+            tags: checkpoint.tags.clone(),
+            hash: checkpoint.hash.clone(),
+            created_by_version: checkpoint.created_by_version.clone(),
+            partial: checkpoint.partial,
+            errors,
+            deleted: Vec::new(),
+            base: None,
+        };
+        let Some(base) = base else {
+            return write_export(file, &exported, root, None);
+        };
+        with_materialized(&config, &alts_dir, base, |base_root| {
+            let changes = diff_trees(base_root, root)?;
+            exported.deleted = changes
+                .iter()
+                .filter(|change| change.kind == ChangeKind::Removed)
+                .map(|change| change.path.to_string_lossy().into_owned())
+                .collect();
+            exported.base = Some(ExportBase {
+                name: base.to_string(),
+                hash: tree_hash(base_root)?,
+            });
+            write_export(file, &exported, root, Some(&changes))
+        })
+    });
+    if let Err(e) = written {
+        let _ = fs::remove_file(file);
+        return Err(e.context(format!("Failed to write '{}'", file.display())));
+    }
+
+    let size = format_size(fs::metadata(file)?.len(), SizeUnit::Auto);
+    let file = match split {
+        Some(part_size) => &split_export(file, part_size)?,
+        None => file,
+    };
+    match base {
+        Some(base) => info!(
+            "Exported checkpoint '{}' relative to '{}' to '{}' ({})",
+            name,
+            base,
+            file.display(),
+            size
+        ),
+        None => info!(
+            "Exported checkpoint '{}' to '{}' ({})",
+            name,
+            file.display(),
+            size
+        ),
+    }
+    Ok(())
+}
+
+/// Lists the parts of a split export with their checksums, stored next to
+/// them as `<archive>.parts`
+#[derive(Serialize, Deserialize)]
+struct SplitManifest {
+    /// SHA-256 of the whole archive
+    sha256: String,
+    parts: Vec<SplitPart>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SplitPart {
+    /// File name, in the directory of the manifest
+    file: String,
+    size: u64,
+    sha256: String,
+}
+
+fn split_manifest_path(file: &Path) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(SPLIT_MANIFEST_SUFFIX);
+    PathBuf::from(path)
+}
+
+/// `<file>.001` for the first part
+fn split_part_path(file: &Path, number: usize) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(format!(".{:03}", number));
+    PathBuf::from(path)
+}
+
+/// Replaces the archive `file` with parts of at most `part_size` bytes and
+/// their manifest, returning the manifest's path. On failure the archive is
+/// removed along with any parts written.
+fn split_export(file: &Path, part_size: u64) -> Result<PathBuf> {
+    let manifest_path = split_manifest_path(file);
+    let mut written = Vec::new();
+    let parts = write_split_parts(file, part_size, &mut written).and_then(|manifest| {
+        let parts = manifest.parts.len();
+        fs::write(&manifest_path, toml::to_string_pretty(&manifest)?)?;
+        fs::remove_file(file)?;
+        Ok(parts)
+    });
+    let parts = match parts {
+        Ok(parts) => parts,
+        Err(e) => {
+            for path in written.iter().chain([&manifest_path, &file.to_path_buf()]) {
+                let _ = fs::remove_file(path);
+            }
+            return Err(e.context(format!("Failed to split '{}'", file.display())));
+        }
+    };
+    info!(
+        "Split into {} part(s) of at most {}",
+        parts,
+        format_size(part_size, SizeUnit::Auto)
+    );
+    Ok(manifest_path)
+}
+
+/// Copies `file` into numbered parts, recording each created path in
+/// `written` so the caller can clean up
+fn write_split_parts(
+    file: &Path,
+    part_size: u64,
+    written: &mut Vec<PathBuf>,
+) -> Result<SplitManifest> {
+    let mut archive = fs::File::open(file)?;
+    let length = archive.metadata()?.len();
+    let mut total = Sha256::new();
+    let mut parts = Vec::new();
+    let mut buffer = vec![0; 1 << 16];
+    // An empty archive still gets one (empty) part
+    while parts.is_empty() || (parts.len() as u64) * part_size < length {
+        let path = split_part_path(file, parts.len() + 1);
+        let mut part = fs::File::create_new(&path)
+            .with_context(|| format!("Failed to create '{}'", path.display()))?;
+        written.push(path.clone());
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        while size < part_size {
+            let wanted = buffer.len().min((part_size - size) as usize);
+            let read = archive.read(&mut buffer[..wanted])?;
+            if read == 0 {
+                break;
+            }
+            part.write_all(&buffer[..read])?;
+            hasher.update(&buffer[..read]);
+            total.update(&buffer[..read]);
+            size += read as u64;
+        }
+        part.sync_all()?;
+        parts.push(SplitPart {
+            file: path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        });
+    }
+    Ok(SplitManifest {
+        sha256: format!("{:x}", total.finalize()),
+        parts,
+    })
+}
+
+/// Opens an archive written by `alts export`. A split export is named by its
+/// manifest, or by the archive it was split from.
+fn open_export(file: &Path) -> Result<Box<dyn Read>> {
+    let manifest = split_manifest_path(file);
+    if file.to_string_lossy().ends_with(SPLIT_MANIFEST_SUFFIX) {
+        return join_split_parts(file);
+    }
+    if fs::symlink_metadata(file).is_err() && manifest.is_file() {
+        return join_split_parts(&manifest);
+    }
+    let reader =
+        fs::File::open(file).with_context(|| format!("Failed to open '{}'", file.display()))?;
+    Ok(Box::new(reader))
+}
+
+/// Checks every part listed in a split export's manifest, then returns a
+/// reader over the reassembled archive
+fn join_split_parts(manifest_path: &Path) -> Result<Box<dyn Read>> {
+    let manifest: SplitManifest = toml::from_str(
+        &fs::read_to_string(manifest_path)
+            .with_context(|| format!("Failed to read '{}'", manifest_path.display()))?,
+    )
+    .with_context(|| format!("Failed to parse '{}'", manifest_path.display()))?;
+    let dir = manifest_path.parent().unwrap_or(Path::new(""));
+
+    let mut total = Sha256::new();
+    let mut paths = Vec::new();
+    for part in &manifest.parts {
+        if Path::new(&part.file).file_name() != Some(std::ffi::OsStr::new(&part.file)) {
+            return Err(anyhow::anyhow!("Invalid part name '{}'", part.file));
+        }
+        let path = dir.join(&part.file);
+        let mut reader =
+            fs::File::open(&path).with_context(|| format!("Missing part '{}'", path.display()))?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; 1 << 16];
+        let mut size = 0;
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            total.update(&buffer[..read]);
+            size += read as u64;
+        }
+        if size != part.size || format!("{:x}", hasher.finalize()) != part.sha256 {
+            return Err(failure(
+                FailureKind::VerificationFailed,
+                format!("Part '{}' is damaged or incomplete", path.display()),
+            ));
+        }
+        paths.push(path);
+    }
+    if format!("{:x}", total.finalize()) != manifest.sha256 {
+        return Err(failure(
+            FailureKind::VerificationFailed,
+            format!(
+                "Parts listed in '{}' do not add up to the exported archive",
+                manifest_path.display()
+            ),
+        ));
+    }
+
+    let mut joined: Box<dyn Read> = Box::new(std::io::empty());
+    for path in paths {
+        let part = fs::File::open(&path)
+            .with_context(|| format!("Failed to open '{}'", path.display()))?;
+        joined = Box::new(joined.chain(part));
+    }
+    Ok(joined)
+}
+
+/// Writes the export archive: the metadata, then the whole tree at `root` or,
+/// given `changes` against a base, only the added and modified entries
+fn write_export(
+    file: &Path,
+    exported: &ExportedCheckpoint,
+    root: &Path,
+    changes: Option<&[TreeChange]>,
+) -> Result<()> {
+    let metadata =
+        toml::to_string_pretty(exported).context("Failed to serialize checkpoint metadata")?;
+    let encoder =
+        flate2::write::GzEncoder::new(fs::File::create(file)?, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder.follow_symlinks(false);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(metadata.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(Utc::now().timestamp().try_into().unwrap_or_default());
+    header.set_cksum();
+    builder.append_data(&mut header, EXPORT_METADATA_FILE, metadata.as_bytes())?;
+    match changes {
+        None => builder.append_dir_all(EXPORT_TREE_DIR, root)?,
+        Some(changes) => {
+            builder.append_dir(EXPORT_TREE_DIR, root)?;
+            // Sorted by path, so directories precede their entries
+            for change in changes.iter().filter(|c| c.kind != ChangeKind::Removed) {
+                builder.append_path_with_name(
+                    root.join(&change.path),
+                    Path::new(EXPORT_TREE_DIR).join(&change.path),
+                )?;
+            }
+        }
+    }
+    builder.into_inner()?.finish()?.sync_all()?;
+    Ok(())
+}
+
+fn import(file: &Path, rename: bool, onto: Option<&str>) -> Result<()> {
+    let mut config = load_config()?;
+    let alts_dir = repo_dir()?.join(ALTS_DIR);
+    let storage = configured_storage(&config)?;
+
+    let onto = onto.map(|base| resolve_checkpoint_name(&config, base).to_string());
+
+    let staging = alts_dir.join(format!(".import-{}", std::process::id()));
+    let imported = import_staged(
+        &config,
+        &alts_dir,
+        file,
+        &staging,
+        rename,
+        onto.as_deref(),
+        storage,
+    );
+    let _ = remove_path(&staging);
+    let (name, exported, stored_bytes) = imported?;
+
+    config.checkpoints.insert(
+        name.clone(),
+        Checkpoint {
+            timestamp: exported.timestamp,
+            message: exported.message,
+            tags: exported.tags,
+            hash: exported.hash,
+            delta_base: None,
+            storage,
+            created_by_version: exported.created_by_version,
+            stored_size: Some(stored_bytes),
+            linked_from: None,
+            partial: exported.partial,
+            source_dir: None,
+        },
+    );
+    save_config(&config)?;
+    record_event(&alts_dir, "import", &[&name]);
+    let onto = onto
+        .map(|base| format!(" onto '{}'", base))
+        .unwrap_or_default();
+    if name == exported.name {
+        info!("Imported checkpoint '{}'{}", name, onto);
+    } else {
+        info!(
+            "Imported checkpoint '{}' as '{}'{}",
+            exported.name, name, onto
+        );
+    }
+    Ok(())
+}
+
+/// Unpacks `file` into `staging` and moves its tree into `.alts` under a
+/// free name, returning that name, the exported metadata and the stored size.
+/// A relative export is first applied to a copy of `onto`.
+fn import_staged(
+    config: &Config,
+    alts_dir: &Path,
+    file: &Path,
+    staging: &Path,
+    rename: bool,
+    onto: Option<&str>,
+    storage: Storage,
+) -> Result<(String, ExportedCheckpoint, u64)> {
+    let reader = open_export(file)?;
+    fs::create_dir_all(staging).context("Failed to create staging directory")?;
+    extract_tar(flate2::read::GzDecoder::new(reader), staging)
+        .map_err(|e| anyhow::anyhow!("Failed to extract '{}': {}", file.display(), e))?;
+
+    let metadata_path = staging.join(EXPORT_METADATA_FILE);
+    let tree = staging.join(EXPORT_TREE_DIR);
+    if !metadata_path.is_file() || !tree.is_dir() {
+        return Err(anyhow::anyhow!(
+            "'{}' is not a checkpoint written by 'alts export'",
+            file.display()
+        ));
+    }
+    let exported: ExportedCheckpoint = toml::from_str(&fs::read_to_string(&metadata_path)?)
+        .with_context(|| {
+            format!(
+                "Failed to parse checkpoint metadata in '{}'",
+                file.display()
+            )
+        })?;
+
+    let wanted = validate_checkpoint_name(&exported.name)?;
+    let taken = |name: &str| {
+        config.checkpoints.contains_key(name)
+            || check_name_collision(config, name).is_err()
+            || fs::symlink_metadata(alts_dir.join(name)).is_ok()
+            || fs::symlink_metadata(alts_dir.join(format!("{}.tar.gz", name))).is_ok()
+    };
+    let name = if !taken(&wanted) {
+        wanted
+    } else if rename {
+        (2..)
+            .map(|n| format!("{}_{}", wanted, n))
+            .find(|name| !taken(name))
+            .expect("suffixes are unbounded")
+    } else {
+        return Err(failure(
+            FailureKind::AlreadyExists,
+            format!(
+                "Checkpoint name '{}' already exists; pass --rename to import it under a new name",
+                wanted
+            ),
+        ));
+    };
+
+    let tree = match (&exported.base, onto) {
+        (None, None) => tree,
+        (Some(base), None) => {
+            return Err(anyhow::anyhow!(
+                "'{}' only holds changes against checkpoint '{}'; pass --onto <BASE>",
+                file.display(),
+                base.name
+            ));
+        }
+        (None, Some(_)) => {
+            return Err(anyhow::anyhow!(
+                "'{}' holds a full checkpoint; import it without --onto",
+                file.display()
+            ));
+        }
+        (Some(base), Some(onto)) => {
+            let rebuilt = staging.join(".onto");
+            if fs::symlink_metadata(&rebuilt).is_ok() {
+                remove_path(&rebuilt)?;
+            }
+            materialize(config, alts_dir, onto, &rebuilt)?;
+            if tree_hash(&rebuilt)? != base.hash {
+                return Err(anyhow::anyhow!(
+                    "Checkpoint '{}' does not match '{}', the base '{}' was exported against",
+                    onto,
+                    base.name,
+                    file.display()
+                ));
+            }
+            if fs::symlink_metadata(rebuilt.join(ERRORS_FILE)).is_ok() {
+                remove_path(&rebuilt.join(ERRORS_FILE))?;
+            }
+            apply_export_changes(&tree, &exported.deleted, &rebuilt)?;
+            rebuilt
+        }
+    };
+
+    // The metadata is authoritative for what was skipped
+    if let Some(errors) = &exported.errors {
+        fs::write(tree.join(ERRORS_FILE), errors)
+            .with_context(|| format!("Failed to write {}", ERRORS_FILE))?;
+    }
+    let checksums = file_checksums(&tree)?;
+    fs::rename(&tree, alts_dir.join(&name)).context("Failed to move imported checkpoint")?;
+    let stored_bytes = store_checkpoint(storage, alts_dir, &name, &checksums)?;
+    Ok((name, exported, stored_bytes))
+}
+
+/// Turns the base tree at `dst` into the exported checkpoint: removes the
+/// `deleted` paths, then moves in the changed entries unpacked at `changes`
+fn apply_export_changes(changes: &Path, deleted: &[String], dst: &Path) -> Result<()> {
+    for path in deleted {
+        if !is_contained(Path::new(path)) {
+            return Err(anyhow::anyhow!(
+                "Deleted path '{}' would escape the checkpoint directory",
+                path
+            ));
+        }
+        let path = dst.join(path);
+        if fs::symlink_metadata(&path).is_ok() {
+            remove_path(&path)?;
+        }
+    }
+    overlay_tree(changes, dst)
+}
+
+/// Moves every entry of `src` into `dst`, replacing what is there except
+/// directories, which are merged
+fn overlay_tree(src: &Path, dst: &Path) -> Result<()> {
+    for entry in fs::read_dir(src).context("Failed to read directory")? {
+        let entry = entry?;
+        let target = dst.join(entry.file_name());
+        let existing = fs::symlink_metadata(&target).ok();
+        if entry.file_type()?.is_dir() && existing.as_ref().is_some_and(|m| m.is_dir()) {
+            overlay_tree(&entry.path(), &target)?;
+            continue;
+        }
+        if existing.is_some() {
+            remove_path(&target)?;
+        }
+        fs::rename(entry.path(), &target)
+            .with_context(|| format!("Failed to move '{}'", target.display()))?;
+    }
+    Ok(())
+}
+
+/// Appends a line `<time>\t<operation>\t<names...>` to the history log. The
+/// operation has already happened, so failing to record it only warns.
+fn record_event(alts_dir: &Path, operation: &str, names: &[&str]) {
+    let line = format!(
+        "{}\t{}\t{}\n",
+        Utc::now().to_rfc3339(),
+        operation,
+        names.join("\t")
+    );
+    let written = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(alts_dir.join(HISTORY_FILE))
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+    if let Err(e) = written {
+        warn!(
+            "Failed to record '{}' in {}: {}",
+            operation, HISTORY_FILE, e
+        );
+    }
+}
+
+/// Prints the history log, newest operation first
+fn reflog(time_zone: Option<TimeZoneArg>) -> Result<()> {
+    load_config()?;
+    let current_dir = repo_dir()?;
+    let path = current_dir.join(ALTS_DIR).join(HISTORY_FILE);
+    if !path.exists() {
+        info!("No operations recorded yet");
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(&path).context("Failed to read history log")?;
+    for line in content.lines().rev() {
+        let mut fields = line.split('\t');
+        let (Some(timestamp), Some(operation)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let names: Vec<&str> = fields.collect();
+        println!(
+            "{}  {:<10} {}",
+            display_timestamp(timestamp, time_zone),
+            operation,
+            names.join(", ")
+        );
+    }
+    Ok(())
+}
+
+fn show_log(max_count: Option<usize>, time_zone: Option<TimeZoneArg>) -> Result<()> {
+    let config = load_config()?;
+    if config.checkpoints.is_empty() {
+        info!("No checkpoints found");
+        return Ok(());
+    }
+
+    let checkpoints = newest_first(&config);
+    let shown = max_count.unwrap_or(checkpoints.len());
+    for (index, (name, checkpoint)) in checkpoints.into_iter().take(shown).enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!("checkpoint {}", name);
+        if checkpoint.timestamp.is_empty() {
+            println!("Date:  unknown");
+        } else {
+            println!(
+                "Date:  {} ({})",
+                display_timestamp(&checkpoint.timestamp, time_zone),
+                format_relative_time(&checkpoint.timestamp)
+            );
+        }
+        if !checkpoint.tags.is_empty() {
+            println!("Tags:  {}", checkpoint.tags.join(", "));
+        }
+        if let Some(message) = &checkpoint.message {
+            println!();
+            for line in message.lines() {
+                println!("    {}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Fills in timestamps of the checkpoints `names`, or of all of them when
+/// empty (`--all-valid`)
+fn touch(names: &[String], force: bool) -> Result<()> {
+    let mut config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    let mut selected = BTreeSet::new();
+    for name in names {
+        let name = resolve_checkpoint_name(&config, name);
+        find_checkpoint(&config, &alts_dir, name)?;
+        selected.insert(name.to_string());
+    }
+
+    let mut updated = Vec::new();
+    for (name, checkpoint) in &mut config.checkpoints {
+        if !selected.is_empty() && !selected.contains(name) {
+            continue;
+        }
+        if !force && DateTime::parse_from_rfc3339(&checkpoint.timestamp).is_ok() {
+            continue;
+        }
+        let path = checkpoint.storage_path(&alts_dir, name);
+        let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+            warn!("Skipping '{}': its data is missing", name);
+            continue;
+        };
+        let timestamp = DateTime::<Utc>::from(modified).to_rfc3339();
+        let previous = if checkpoint.timestamp.is_empty() {
+            "missing"
+        } else {
+            checkpoint.timestamp.as_str()
+        };
+        info!(
+            "Updated '{}': {} -> {} (from directory mtime)",
+            name, previous, timestamp
+        );
+        checkpoint.timestamp = timestamp;
+        updated.push(name.clone());
+    }
+
+    if updated.is_empty() {
+        info!("No checkpoint timestamps needed updating");
+        return Ok(());
+    }
+    save_config(&config)?;
+    let names: Vec<&str> = updated.iter().map(String::as_str).collect();
+    record_event(&alts_dir, "touch", &names);
+    info!("Updated {} checkpoint timestamp(s)", updated.len());
+    Ok(())
+}
+
+fn debug_tree_hash(name: &str) -> Result<()> {
+    let config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+    let name = resolve_checkpoint_name(&config, name);
+
+    println!(
+        "{}",
+        with_materialized(&config, &alts_dir, name, tree_hash)?
+    );
+    Ok(())
+}
+
+/// Counts files that make allocated and apparent sizes disagree beyond block
+/// rounding: files with several hard links, and sparse files
+#[cfg(unix)]
+fn size_anomalies(dir: &Path) -> Result<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut hardlinked = 0;
+    let mut sparse = 0;
+    for entry in fs::read_dir(dir).context("Failed to read directory")? {
+        let entry = entry?;
+        let metadata = fs::symlink_metadata(entry.path())?;
+        if metadata.is_dir() {
+            let (h, s) = size_anomalies(&entry.path())?;
+            hardlinked += h;
+            sparse += s;
+        } else if metadata.is_file() {
+            if metadata.nlink() > 1 {
+                hardlinked += 1;
+            }
+            if metadata.blocks() * 512 < metadata.len() {
+                sparse += 1;
+            }
+        }
+    }
+    Ok((hardlinked, sparse))
+}
+
+#[cfg(not(unix))]
+fn size_anomalies(_dir: &Path) -> Result<(u64, u64)> {
+    Ok((0, 0))
+}
+
+/// Apparent size in bytes of a checkpoint's data, walked natively so it works
+/// wherever `du` is unavailable; 0 when the path is missing
+fn get_dir_size(path: &Path) -> Result<u64> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    stored_size(path)
+}
+
+/// Allocated size reported by `du -sk`, for comparison in `debug size-compare`
+fn du_size_kb(path: &Path) -> Result<u64> {
+    let output = std::process::Command::new("du")
+        .arg("-sk")
+        .arg(path)
+        .output()
+        .context("Failed to execute du command")?;
+
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("du command failed"));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if let Some(size_str) = stdout.split_whitespace().next() {
+        match size_str.parse::<u64>() {
+            Ok(size_kb) => Ok(size_kb),
+            Err(_) => Err(anyhow::anyhow!("Failed to parse size from du output")),
+        }
+    } else {
+        Err(anyhow::anyhow!("du command returned unexpected output"))
+    }
+}
+
+/// Parses sizes like `500MB`, `1.5 GB` or `1024` (bytes); units are 1024-based
+fn parse_size(s: &str) -> Result<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("Invalid size '{}'", s))?;
+    let multiplier: u64 = match suffix.trim().to_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        "T" | "TB" => 1 << 40,
+        other => return Err(anyhow::anyhow!("Unknown size unit '{}'", other)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Describes a copy as "in 1.23s (4.56 MB/s)"
+fn format_transfer(bytes: u64, elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs_f64();
+    let per_second = if seconds > 0.0 {
+        (bytes as f64 / seconds) as u64
+    } else {
+        bytes
+    };
+    format!(
+        "{} in {:.2}s ({}/s)",
+        format_size(bytes, SizeUnit::Auto),
+        seconds,
+        format_size(per_second, SizeUnit::Auto)
+    )
+}
+
+fn format_size(bytes: u64, unit: SizeUnit) -> String {
+    const UNITS: &[&str] = &["KB", "MB", "GB", "TB"];
+    let kb = bytes as f64 / 1024.0;
+
+    match unit {
+        SizeUnit::Bytes => format!("{} B", bytes),
+        SizeUnit::Kb => format!("{:.2} KB", kb),
+        SizeUnit::Mb => format!("{:.2} MB", kb / 1024.0),
+        SizeUnit::Gb => format!("{:.2} GB", kb / (1024.0 * 1024.0)),
+        SizeUnit::Auto => {
+            let mut size = kb;
+            let mut unit_index = 0;
+
+            while size >= 1024.0 && unit_index < UNITS.len() - 1 {
+                size /= 1024.0;
+                unit_index += 1;
+            }
+
+            format!("{:.2} {}", size, UNITS[unit_index])
+        }
+    }
+}
+
+fn prune(args: &PruneArgs, time_zone: Option<TimeZoneArg>) -> Result<()> {
+    let mut config = load_config()?;
+    let current_dir = repo_dir()?;
+    let alts_dir = current_dir.join(ALTS_DIR);
+
+    // Orphans pile up just as well when the index is empty
+    if args.orphans {
+        return prune_orphans(&config, &alts_dir, args);
+    }
+
+    if config.checkpoints.is_empty() {
+        info!("No checkpoints to prune");
+        return Ok(());
+    }
+
+    let protected = protected_checkpoints(&config, &args.except);
+
+    if args.keep_latest_per_day {
+        let time_zone = time_zone.unwrap_or(TimeZoneArg::Local);
+        return prune_keep_latest_per_day(
+            &mut config,
+            &alts_dir,
+            time_zone,
+            &protected,
+            args.force,
+        );
+    }
+    if args.dedup {
+        return prune_dedup(&mut config, &alts_dir, args, &protected);
+    }
+    if let Some(age) = args.older_than {
+        return prune_older_than(&mut config, &alts_dir, age, args, &protected);
+    }
+
+    info!("Checking checkpoints...");
+    let mut to_remove: Vec<String> = Vec::new();
+
+    for (name, checkpoint) in &config.checkpoints {
+        if checkpoint.storage_path(&alts_dir, name).exists() {
+            info!("  Found: {}", name);
+        } else if protected.contains(name) {
+            info!("  Not found, but kept by --except: {}", name);
+        } else {
+            info!("  Not found: {}", name);
+            to_remove.push(name.clone());
+        }
+    }
+
+    // Remove unfound checkpoints from index
+    for name in &to_remove {
+        config.checkpoints.remove(name);
+    }
+
+    if !to_remove.is_empty() {
+        save_config(&config)?;
+        info!(
+            "Removed {} unfound checkpoint(s) from index",
+            to_remove.len()
+        );
+    } else {
+        info!("All checkpoints found, nothing to remove");
+    }
+
+    Ok(())
+}
+
+/// Resolves `--except` arguments (names or aliases) to checkpoint names
+fn protected_checkpoints(config: &Config, except: &[String]) -> BTreeSet<String> {
+    let mut protected = BTreeSet::new();
+    for name in except {
+        let resolved = resolve_checkpoint_name(config, name);
+        if config.checkpoints.contains_key(resolved) {
+            protected.insert(resolved.to_string());
+        } else {
+            warn!("--except '{}' does not match any checkpoint", name);
+        }
+    }
+    protected
+}
+
+fn prune_keep_latest_per_day(
+    config: &mut Config,
+    alts_dir: &Path,
+    time_zone: TimeZoneArg,
+    protected: &BTreeSet<String>,
+    force: bool,
+) -> Result<()> {
+    let mut days: BTreeMap<NaiveDate, Vec<(String, String)>> = BTreeMap::new();
+    for (name, checkpoint) in &config.checkpoints {
+        match time_zone.date_of(&checkpoint.timestamp) {
+            Some(date) => days
+                .entry(date)
+                .or_default()
+                .push((checkpoint.timestamp.clone(), name.clone())),
+            None => warn!(
+                "Keeping '{}': unparseable timestamp '{}'",
+                name, checkpoint.timestamp
+            ),
+        }
+    }
+
+    // Decide everything up front so the confirmation gate sees the whole plan
+    let mut plan = Vec::new();
+    for (date, mut entries) in days {
+        // Newest first; RFC3339 strings from `checkpoint` sort chronologically
+        entries.sort_by(|a, b| b.0.cmp(&a.0));
+        let mut entries = entries.into_iter().map(|(_, name)| name);
+        let kept = entries.next().expect("days are never empty");
+        let mut doomed = Vec::new();
+        for name in entries {
+            if protected.contains(&name) {
+                info!("Keeping '{}': listed in --except", name);
+            } else if let Some(dependent) = delta_dependent(config, &name) {
+                info!(
+                    "Keeping '{}': '{}' stores deltas against it",
+                    name, dependent
+                );
+            } else {
+                doomed.push(name);
+            }
+        }
+        plan.push((date, kept, doomed));
+    }
+
+    let doomed: Vec<&String> = plan.iter().flat_map(|(_, _, d)| d).collect();
+    confirm_bulk_delete(config, alts_dir, &doomed, force)?;
+
+    let mut deleted = 0;
+    for (date, kept, doomed) in &plan {
+        for name in doomed {
+            delete_checkpoint(config, alts_dir, name)?;
+        }
+        info!("{}: kept '{}', deleted {}", date, kept, doomed.len());
+        deleted += doomed.len();
+    }
+
+    save_config(config)?;
+    info!("Deleted {} checkpoint(s)", deleted);
+    Ok(())
+}
+
+/// Deletes what `orphaned_entries` finds in `.alts`, after asking unless
+/// `--force` is given
+fn prune_orphans(config: &Config, alts_dir: &Path, args: &PruneArgs) -> Result<()> {
+    let work = work_entries(alts_dir)?;
+    if !work.is_empty() {
+        info!(
+            "Leaving {} item(s) of running or interrupted alts commands alone; see 'alts fsck'",
+            work.len()
+        );
+    }
+    let orphans = orphaned_entries(config, alts_dir)?;
+    if orphans.is_empty() {
+        info!("No orphaned items in {}", ALTS_DIR);
+        return Ok(());
+    }
+
+    let mut total = 0;
+    for name in &orphans {
+        let size = stored_size(&alts_dir.join(name)).unwrap_or(0);
+        total += size;
+        info!("  {} ({})", name, format_size(size, SizeUnit::Auto));
+    }
+    if args.dry_run {
+        info!(
+            "Would delete {} orphaned item(s), {}",
+            orphans.len(),
+            format_size(total, SizeUnit::Auto)
+        );
+        return Ok(());
+    }
+    if !args.force
+        && !confirm(&format!(
+            "Delete {} item(s) in {} that are not in the index?",
+            orphans.len(),
+            ALTS_DIR
+        ))?
+    {
+        return Err(anyhow::anyhow!(
+            "Aborted; nothing was deleted (use --force to skip the question)"
+        ));
+    }
+
+    for name in &orphans {
+        remove_path(&alts_dir.join(name))?;
+    }
+    info!(
+        "Deleted {} orphaned item(s), {}",
+        orphans.len(),
+        format_size(total, SizeUnit::Auto)
+    );
+    Ok(())
+}
+
+/// Deletes checkpoints whose timestamp is more than `age` in the past
+fn prune_older_than(
+    config: &mut Config,
+    alts_dir: &Path,
+    age: Duration,
+    args: &PruneArgs,
+    protected: &BTreeSet<String>,
+) -> Result<()> {
+    let cutoff = Utc::now() - chrono::Duration::from_std(age).context("Duration is too long")?;
+    let mut aged = Vec::new();
+    for (name, checkpoint) in &config.checkpoints {
+        match DateTime::parse_from_rfc3339(&checkpoint.timestamp) {
+            Ok(time) if time < cutoff => aged.push((time, name.clone())),
+            Ok(_) => {}
+            Err(_) => warn!(
+                "Keeping '{}': unparseable timestamp '{}'",
+                name, checkpoint.timestamp
+            ),
+        }
+    }
+    // Oldest first
+    aged.sort();
+
+    let mut doomed = Vec::new();
+    for (_, name) in aged {
+        if protected.contains(&name) {
+            info!("Keeping '{}': listed in --except", name);
+        } else if let Some(dependent) = delta_dependent(config, &name) {
+            info!(
+                "Keeping '{}': '{}' stores deltas against it",
+                name, dependent
+            );
+        } else {
+            doomed.push(name);
+        }
+    }
+    if doomed.is_empty() {
+        info!("No checkpoints older than the cutoff");
+        return Ok(());
+    }
+
+    if args.dry_run {
+        for name in &doomed {
+            info!(
+                "Would delete '{}' ({})",
+                name, config.checkpoints[name].timestamp
+            );
+        }
+        info!("Would delete {} checkpoint(s)", doomed.len());
+        return Ok(());
+    }
+
+    let doomed_refs: Vec<&String> = doomed.iter().collect();
+    confirm_bulk_delete(config, alts_dir, &doomed_refs, args.force)?;
+    for name in &doomed {
+        delete_checkpoint(config, alts_dir, name)?;
+    }
+    save_config(config)?;
+    info!("Deleted {} checkpoint(s)", doomed.len());
+    Ok(())
+}
+
+/// Groups checkpoints by tree hash and deletes all but one of each group
+fn prune_dedup(
+    config: &mut Config,
+    alts_dir: &Path,
+    args: &PruneArgs,
+    protected: &BTreeSet<String>,
+) -> Result<()> {
+    info!("Hashing checkpoints...");
+    let mut groups: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+    for (name, checkpoint) in &config.checkpoints {
+        if let Some(hash) = stored_or_computed_hash(config, alts_dir, name, checkpoint)? {
+            groups
+                .entry(hash)
+                .or_default()
+                .push((checkpoint.timestamp.clone(), name.clone()));
+        }
+    }
+
+    // Pairs of (deleted, kept) names
+    let mut plan = Vec::new();
+    for (hash, mut entries) in groups {
+        if entries.len() < 2 {
+            continue;
+        }
+        // Kept checkpoint first; RFC3339 strings from `checkpoint` sort chronologically
+        entries.sort();
+        if !args.keep_oldest {
+            entries.reverse();
+        }
+        let mut entries = entries.into_iter().map(|(_, name)| name);
+        let kept = entries.next().expect("groups have at least two entries");
+        info!(
+            "{} checkpoints share content {}, keeping '{}'",
+            entries.len() + 1,
+            &hash[..DEFAULT_HASH_PREFIX_LEN],
+            kept
+        );
+        for name in entries {
+            if protected.contains(&name) {
+                info!("  Keeping '{}': listed in --except", name);
+            } else if let Some(dependent) = delta_dependent(config, &name) {
+                info!(
+                    "  Keeping '{}': '{}' stores deltas against it",
+                    name, dependent
+                );
+            } else {
+                plan.push((name, kept.clone()));
+            }
+        }
+    }
+
+    if plan.is_empty() {
+        info!("No duplicate checkpoints found");
+        return Ok(());
+    }
+    let doomed: Vec<&String> = plan.iter().map(|(name, _)| name).collect();
+    confirm_bulk_delete(config, alts_dir, &doomed, args.force)?;
+
+    let mut saved = 0;
+    for (name, kept) in &plan {
+        saved += stored_size(&config.checkpoints[name].storage_path(alts_dir, name))?;
+        if args.alias_removed {
+            for target in config.aliases.values_mut() {
+                if target == name {
+                    *target = kept.clone();
+                }
+            }
+        }
+        delete_checkpoint(config, alts_dir, name)?;
+        if args.alias_removed {
+            config.aliases.insert(name.clone(), kept.clone());
+            info!("  Alias '{}' now points to '{}'", name, kept);
+        }
+    }
+
+    save_config(config)?;
+    info!(
+        "Deleted {} duplicate checkpoint(s), freeing {}",
+        plan.len(),
+        format_size(saved, SizeUnit::Auto)
+    );
+    Ok(())
+}
+
+/// Asks for confirmation when a bulk deletion exceeds the configured
+/// `confirm_if_deleting_more_than` count or `confirm_if_reclaiming_more_than`
+/// size. `--force` skips the gate; without a terminal the deletion is refused.
+fn confirm_bulk_delete(
+    config: &Config,
+    alts_dir: &Path,
+    names: &[&String],
+    force: bool,
+) -> Result<()> {
+    if force || names.is_empty() {
+        return Ok(());
+    }
+
+    let mut reasons = Vec::new();
+    if let Some(limit) = config.confirm_if_deleting_more_than
+        && names.len() > limit
+    {
+        reasons.push(format!(
+            "deleting {} checkpoint(s) exceeds confirm_if_deleting_more_than = {}",
+            names.len(),
+            limit
+        ));
+    }
+    if let Some(limit) = &config.confirm_if_reclaiming_more_than {
+        let limit_bytes = parse_size(limit)
+            .with_context(|| format!("Invalid confirm_if_reclaiming_more_than '{}'", limit))?;
+        let mut bytes = 0;
+        for name in names {
+            let Some(checkpoint) = config.checkpoints.get(name.as_str()) else {
+                continue;
+            };
+            let checkpoint_path = checkpoint.storage_path(alts_dir, name);
+            if checkpoint_path.exists() {
+                bytes += stored_size(&checkpoint_path)?;
+            }
+        }
+        if bytes > limit_bytes {
+            reasons.push(format!(
+                "reclaiming {} exceeds confirm_if_reclaiming_more_than = {}",
+                format_size(bytes, SizeUnit::Auto),
+                limit
+            ));
+        }
+    }
+
+    if reasons.is_empty() {
+        return Ok(());
+    }
+    for reason in &reasons {
+        warn!("{}", reason);
+    }
+    if !confirm("Delete these checkpoints?")? {
+        return Err(anyhow::anyhow!(
+            "Aborted; nothing was deleted. Re-run interactively or pass --force"
+        ));
+    }
+    Ok(())
+}
+
+fn delete(names: &[String], force: bool) -> Result<()> {
+    let mut config = load_config()?;
+    let alts_dir = repo_dir()?.join(ALTS_DIR);
+
+    // Check every name before deleting anything
+    let mut unique: Vec<String> = Vec::new();
+    for name in names {
+        // Aliases are deliberately not followed here, only `latest` and `@-N`
+        let name = match relative_checkpoint(&config, name) {
+            Some(relative) if !config.checkpoints.contains_key(name) => relative,
+            _ => name.as_str(),
+        };
+        if !config.checkpoints.contains_key(name) {
+            return Err(failure(
+                FailureKind::NotFound,
+                format!("Checkpoint '{}' not found in index", name),
+            ));
+        }
+        if !unique.iter().any(|seen| seen == name) {
+            unique.push(name.to_string());
+        }
+    }
+    // Dependents deleted in the same call do not hold their base back
+    for name in &unique {
+        if let Some((dependent, _)) = config.checkpoints.iter().find(|(dependent, c)| {
+            c.delta_base.as_deref() == Some(name.as_str()) && !unique.contains(dependent)
+        }) {
+            return Err(anyhow::anyhow!(
+                "Cannot delete '{}': checkpoint '{}' stores deltas against it; delete that first",
+                name,
+                dependent
+            ));
+        }
+    }
+    let doomed: Vec<&String> = unique.iter().collect();
+    confirm_bulk_delete(&config, &alts_dir, &doomed, force)?;
+
+    // Delete dependents before the bases they store deltas against; a cycle
+    // in a hand-edited index is left for `delete_checkpoint` to refuse
+    while !unique.is_empty() {
+        let index = unique
+            .iter()
+            .position(|name| delta_dependent(&config, name).is_none())
+            .unwrap_or(0);
+        let name = unique.remove(index);
+        let stored = config.checkpoints[name.as_str()].storage_path(&alts_dir, &name);
+        if fs::symlink_metadata(stored).is_err() {
+            warn!(
+                "Data of checkpoint '{}' was already gone; removing it from the index",
+                name
+            );
+        }
+        delete_checkpoint(&mut config, &alts_dir, &name)?;
+    }
+    save_config(&config)
+}
+
+/// A checkpoint storing deltas against `name`, which therefore must be kept
+fn delta_dependent<'a>(config: &'a Config, name: &str) -> Option<&'a String> {
+    config
+        .checkpoints
+        .iter()
+        .find(|(_, c)| c.delta_base.as_deref() == Some(name))
+        .map(|(dependent, _)| dependent)
+}
+
+/// Removes a checkpoint's data (if still present) and its index entry.
+/// The caller is responsible for saving the config.
+fn delete_checkpoint(config: &mut Config, alts_dir: &Path, name: &str) -> Result<()> {
+    // A hand-edited index must never make us delete outside `.alts`
+    if Path::new(name).file_name() != Some(std::ffi::OsStr::new(name)) {
+        return Err(anyhow::anyhow!(
+            "Refusing to delete invalid checkpoint name '{}'",
+            name
+        ));
+    }
+    if let Some(dependent) = delta_dependent(config, name) {
+        return Err(anyhow::anyhow!(
+            "Refusing to delete '{}': checkpoint '{}' stores deltas against it",
+            name,
+            dependent
+        ));
+    }
+    if let Some(checkpoint) = config.checkpoints.remove(name) {
+        let checkpoint_path = checkpoint.storage_path(alts_dir, name);
+        if fs::symlink_metadata(&checkpoint_path).is_ok() {
+            remove_path(&checkpoint_path)?;
+        }
+        let checksums = checksums_path(alts_dir, name);
+        if checksums.exists() {
+            remove_path(&checksums)?;
+        }
+        if checkpoint.storage == Storage::Objects {
+            collect_object_garbage(config, alts_dir)?;
+        }
+        // Linked files keep their data through the remaining links
+        for other in config.checkpoints.values_mut() {
+            if other.linked_from.as_deref() == Some(name) {
+                other.linked_from = None;
+            }
+        }
+        record_event(alts_dir, "delete", &[name]);
+    }
+    info!("Deleted checkpoint '{}'", name);
+    config.aliases.retain(|label, target| {
+        let dangling = target == name;
+        if dangling {
+            info!("Removed alias '{}' pointing to '{}'", label, name);
+        }
+        !dangling
+    });
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+struct CopyOptions {
+    /// Copy the content behind symlinks whose targets lie outside the tracked tree
+    snapshot_symlink_targets: bool,
+    /// Copy the content behind every symlink, inside the tracked tree or not
+    dereference: bool,
+    /// Gitignore-style patterns of paths to leave out
+    exclude_patterns: Vec<String>,
+    /// Files containing further exclude patterns
+    exclude_from: Vec<PathBuf>,
+    /// When set, only these paths (and the directories leading to them) are copied
+    only_paths: Option<BTreeSet<PathBuf>>,
+    /// Link destination files whose sources share an inode instead of copying them again
+    preserve_hardlinks: bool,
+    /// Leave out directories named `.alts`, so a repository never snapshots itself
+    exclude_dotalts: bool,
+    /// Hash each file while copying and check the written copy against it
+    verify: bool,
+    /// Record entries that fail to copy in `CopyStats::failed` and go on
+    skip_errors: bool,
+    /// Walk and count as usual but write nothing
+    dry_run: bool,
+    /// Log nothing about individual entries, for planning passes that the
+    /// real copy follows
+    quiet: bool,
+    /// Log progress lines at most this often
+    progress_interval: Option<Duration>,
+    /// Files an earlier `plan_copy` found, the total for progress lines;
+    /// counted up front when missing
+    planned_files: Option<u64>,
+    /// Earlier copy to hard link files from when the file at the same path
+    /// there has the source's size, mtime and mode
+    link_unchanged_from: Option<PathBuf>,
+    /// Leave out regular files larger than this many bytes
+    max_file_size: Option<u64>,
+}
+
+#[derive(Default)]
+struct CopyStats {
+    files: u64,
+    bytes: u64,
+    /// Files whose copy was re-read and checked against the source hash
+    verified: u64,
+    /// Files hard linked from `link_unchanged_from` instead of copied
+    linked: u64,
+    /// Files left out for being larger than `max_file_size`, with their sizes
+    oversized: Vec<(PathBuf, u64)>,
+    /// Source paths left out with `skip_errors`, with the reason
+    failed: Vec<(PathBuf, String)>,
+}
+
+/// Copies a file while hashing what is read, then re-reads the copy and fails
+/// unless both hashes agree
+fn copy_file_verified(src: &Path, dst: &Path) -> Result<u64> {
+    let mut reader =
+        fs::File::open(src).with_context(|| format!("Failed to open '{}'", src.display()))?;
+    let mut writer =
+        fs::File::create(dst).with_context(|| format!("Failed to create '{}'", dst.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut bytes = 0;
+    loop {
+        let n = reader.read(&mut buffer).context("Failed to copy file")?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buffer[..n]);
+        writer
+            .write_all(&buffer[..n])
+            .context("Failed to copy file")?;
+        bytes += n as u64;
+    }
+    writer.sync_all().context("Failed to flush copied file")?;
+    fs::set_permissions(dst, reader.metadata()?.permissions())
+        .context("Failed to copy file permissions")?;
+
+    if format!("{:x}", hasher.finalize()) != hash_file(dst)? {
+        return Err(failure(
+            FailureKind::VerificationFailed,
+            format!(
+                "Verification failed: '{}' does not match its source '{}'",
+                dst.display(),
+                src.display()
+            ),
+        ));
+    }
+    Ok(bytes)
+}
+
+/// What copying `src` with `options` would copy, found by a quiet dry run
+fn plan_copy(src: &Path, options: &CopyOptions) -> Result<CopyStats> {
+    let planning = CopyOptions {
+        dry_run: true,
+        quiet: true,
+        progress_interval: None,
+        ..options.clone()
+    };
+    // A dry run never touches the destination
+    copy_dir_recursive(src, Path::new(""), &planning)
+}
 
-    info!("Checkpoint '{}' created successfully", checkpoint_name);
+fn copy_dir_recursive(src: &Path, dst: &Path, options: &CopyOptions) -> Result<CopyStats> {
+    let root = src
+        .canonicalize()
+        .context("Failed to resolve source directory")?;
+    let progress = match options.progress_interval {
+        Some(interval) if !options.dry_run => Some(ProgressLines {
+            total_files: match options.planned_files {
+                Some(files) => files,
+                None => plan_copy(src, options)?.files,
+            },
+            interval,
+            last: Mutex::new(Instant::now()),
+        }),
+        _ => None,
+    };
+    let excludes = build_excludes(&root, options)?;
+    let mut copier = Copier {
+        ancestors: vec![root.clone()],
+        followed_links: 0,
+        repository: repo_dir()
+            .and_then(|dir| Ok(dir.join(ALTS_DIR).canonicalize()?))
+            .ok(),
+        root,
+        options,
+        excludes,
+        stats: CopyStats::default(),
+        copied_inodes: BTreeMap::new(),
+        pending_files: Vec::new(),
+        pending_links: Vec::new(),
+        pending_dirs: Vec::new(),
+        progress,
+    };
+    copier.copy_dir(src, dst, Path::new(""))?;
+    copier.copy_pending()?;
+    if let Some(progress) = &copier.progress {
+        progress.log(copier.stats.files);
+    }
+    Ok(copier.stats)
+}
 
-    Ok(())
+/// Throttled "copied X/Y files" reporting for one copy, shared by the
+/// worker threads
+struct ProgressLines {
+    total_files: u64,
+    interval: Duration,
+    last: Mutex<Instant>,
 }
 
-fn list(time_order: bool, reverse: bool) -> Result<()> {
-    let config = load_config()?;
-    let current_dir = std::env::current_dir()?;
-    let alts_dir = current_dir.join(ALTS_DIR);
+impl ProgressLines {
+    /// Logs a progress line if the interval has passed since the last one
+    fn tick(&self, files: u64) {
+        let Ok(mut last) = self.last.try_lock() else {
+            return;
+        };
+        if last.elapsed() >= self.interval {
+            self.log(files);
+            *last = Instant::now();
+        }
+    }
 
-    if config.checkpoints.is_empty() {
-        info!("No checkpoints found");
-        return Ok(());
+    fn log(&self, files: u64) {
+        let percent = (files * 100).checked_div(self.total_files).unwrap_or(100);
+        info!("Copied {}/{} files ({}%)", files, self.total_files, percent);
     }
+}
 
-    info!("Checkpoints:");
+/// Exclusions every copy of the target gets: `exclude_always`, the target's
+/// `.altsignore`, `max_file_size` and nested `.alts` directories
+fn target_copy_options(config: &Config, target_path: &Path) -> Result<CopyOptions> {
+    let ignore_file = target_path.join(IGNORE_FILE);
+    Ok(CopyOptions {
+        exclude_patterns: config.exclude_always.clone(),
+        exclude_from: ignore_file
+            .is_file()
+            .then_some(ignore_file)
+            .into_iter()
+            .collect(),
+        max_file_size: max_file_size(config)?,
+        exclude_dotalts: true,
+        ..Default::default()
+    })
+}
 
-    // Convert to a vector to allow sorting
-    let mut checkpoints: Vec<(&String, &Checkpoint)> = config.checkpoints.iter().collect();
+/// Combines every ignore source into one matcher. Sources are applied in
+/// order of increasing precedence, so a later `!pattern` can re-include a
+/// path excluded earlier:
+///
+/// 1. `exclude_always` from `alts.toml`
+/// 2. `.altsignore` in the target directory, if present
+/// 3. files passed with `--exclude-from`, in command-line order
+fn build_excludes(root: &Path, options: &CopyOptions) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
 
-    if time_order {
-        // Sort by timestamp
-        checkpoints.sort_by(|a, b| {
-            a.1.timestamp.cmp(&b.1.timestamp)
-        });
+    for pattern in &options.exclude_patterns {
+        builder
+            .add_line(None, pattern)
+            .with_context(|| format!("Invalid exclude pattern '{}'", pattern))?;
+    }
 
-        if reverse {
-            checkpoints.reverse();
+    for path in &options.exclude_from {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read exclude file '{}'", path.display()))?;
+        for line in content.lines() {
+            builder
+                .add_line(Some(path.clone()), line)
+                .with_context(|| format!("Invalid pattern '{}' in '{}'", line, path.display()))?;
         }
-    } else if reverse {
-        // Just reverse the default order (BTreeMap order)
-        checkpoints.reverse();
     }
 
-    for (name, checkpoint) in checkpoints {
-        let checkpoint_path = alts_dir.join(Path::new(name));
-        let exists = checkpoint_path.exists();
-        let status = if exists { "✓" } else { "✗" };
-        println!("  {} {} - {}", status, name, checkpoint.timestamp);
-    }
+    builder.build().context("Failed to build exclude patterns")
+}
 
-    Ok(())
+/// State shared across one recursive copy of the tracked tree
+struct Copier<'a> {
+    root: PathBuf,
+    options: &'a CopyOptions,
+    excludes: Gitignore,
+    /// Canonical path of the directory being copied and of each one above
+    /// it, used to detect symlink cycles
+    ancestors: Vec<PathBuf>,
+    /// How many followed directory symlinks the walk is currently inside
+    followed_links: usize,
+    /// Canonical path of this repository's `.alts`, which is never copied,
+    /// whatever the options say
+    repository: Option<PathBuf>,
+    stats: CopyStats,
+    /// Destination of the first copy of each multiply-linked source inode,
+    /// filled only with `preserve_hardlinks`
+    copied_inodes: BTreeMap<(u64, u64), PathBuf>,
+    /// Regular files found by the walk, copied together by `copy_pending`
+    pending_files: Vec<(PathBuf, PathBuf)>,
+    /// Hard links to create once their first copy exists, as `(first, dst)`
+    pending_links: Vec<(PathBuf, PathBuf)>,
+    /// Copied directories in walk order, with the metadata of their source
+    pending_dirs: Vec<(PathBuf, FileMeta)>,
+    progress: Option<ProgressLines>,
 }
 
-fn info() -> Result<()> {
-    let config = load_config()?;
-    let current_dir = std::env::current_dir()?;
-    let alts_dir = current_dir.join(ALTS_DIR);
+impl Copier<'_> {
+    /// Whether to log about individual entries; quiet planning passes do not
+    fn logging(&self) -> bool {
+        !self.options.quiet
+    }
 
-    println!("Repository Information:");
-    println!("=======================");
-    println!("Target Directory: {}", config.target_dir);
-    println!("Total Checkpoints: {}", config.checkpoints.len());
+    /// Copies the files queued by the walk on the rayon pool, then creates
+    /// the hard links that point at them. Stops at the first failed file
+    /// unless `skip_errors` is set.
+    /// Directory metadata goes last, deepest first, so that neither their
+    /// own mtime nor a read-only mode is disturbed by writing inside them.
+    fn copy_pending(&mut self) -> Result<()> {
+        let files = std::mem::take(&mut self.pending_files);
+        let verify = self.options.verify;
+        let skip_errors = self.options.skip_errors;
+        let progress = self.progress.as_ref();
+        let done = AtomicU64::new(0);
+        let failed = Mutex::new(Vec::new());
+        let bytes = files
+            .par_iter()
+            .map(|(src, dst)| {
+                let copied = if verify {
+                    copy_file_verified(src, dst)
+                } else {
+                    fs::copy(src, dst)
+                        .with_context(|| format!("Failed to copy '{}'", src.display()))
+                };
+                let bytes = match copied.and_then(|bytes| {
+                    FileMeta::read(src)?.apply(dst)?;
+                    Ok(bytes)
+                }) {
+                    Ok(bytes) => bytes,
+                    Err(e) if skip_errors => {
+                        let _ = fs::remove_file(dst);
+                        failed
+                            .lock()
+                            .unwrap()
+                            .push((src.clone(), format!("{:#}", e)));
+                        return Ok(None);
+                    }
+                    Err(e) => return Err(e),
+                };
+                let copied = done.fetch_add(1, Ordering::Relaxed) + 1;
+                if let Some(progress) = progress {
+                    progress.tick(copied);
+                }
+                Ok(Some(bytes))
+            })
+            .collect::<Result<Vec<Option<u64>>>>()?;
+        let copied = bytes.iter().flatten().count() as u64;
+        self.stats.files += copied;
+        self.stats.bytes += bytes.iter().flatten().sum::<u64>();
+        if verify {
+            self.stats.verified += copied;
+        }
+        let mut failed = failed.into_inner().unwrap();
+        failed.sort();
+        self.stats.failed.extend(failed);
 
-    if config.checkpoints.is_empty() {
-        println!("\nNo checkpoints available.");
-        return Ok(());
+        for (first, dst) in std::mem::take(&mut self.pending_links) {
+            let linked = fs::hard_link(&first, &dst)
+                .with_context(|| format!("Failed to create hard link '{}'", dst.display()));
+            self.tolerate(&dst, linked)?;
+            if dst.exists() {
+                self.stats.files += 1;
+            }
+        }
+
+        for (dst, meta) in std::mem::take(&mut self.pending_dirs).iter().rev() {
+            meta.apply(dst)?;
+        }
+        Ok(())
     }
 
-    // Count valid checkpoints and calculate total size
-    let mut valid_count = 0;
-    let mut invalid_count = 0;
-    let mut total_size_kb = 0u64;
+    /// Copies `src` into `dst`; `rel` is the location of `src` inside the checkpoint
+    fn copy_dir(&mut self, src: &Path, dst: &Path, rel: &Path) -> Result<()> {
+        // Listed first so an unreadable directory leaves nothing behind
+        let entries = fs::read_dir(src).context("Failed to read directory")?;
+        if !self.options.dry_run {
+            fs::create_dir_all(dst).context("Failed to create directory")?;
+            self.pending_dirs
+                .push((dst.to_path_buf(), FileMeta::read(src)?));
+        }
 
-    for (name, _checkpoint) in &config.checkpoints {
-        let checkpoint_path = alts_dir.join(Path::new(name));
-        if checkpoint_path.exists() {
-            valid_count += 1;
-            // Get size using du command
-            if let Ok(output) = std::process::Command::new("du")
-                .args(["-sk", checkpoint_path.to_str().unwrap()])
-                .output()
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let src_path = entry.path();
+            let dst_path = dst.join(entry.file_name());
+            let rel_path = rel.join(entry.file_name());
+
+            let canonical = self.ancestors[self.ancestors.len() - 1].join(entry.file_name());
+            if file_type.is_dir() && self.repository.as_ref() == Some(&canonical) {
+                if self.logging() {
+                    info!("Skipping this repository: {}", src_path.display());
+                }
+                continue;
+            }
+            if self.options.exclude_dotalts && file_type.is_dir() && entry.file_name() == ALTS_DIR {
+                if self.logging() {
+                    info!("Excluding repository directory: {}", src_path.display());
+                }
+                continue;
+            }
+
+            if self
+                .excludes
+                .matched(&rel_path, file_type.is_dir())
+                .is_ignore()
             {
-                if output.status.success() {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-                    if let Some(size_str) = stdout.split_whitespace().next() {
-                        if let Ok(size_kb) = size_str.parse::<u64>() {
-                            total_size_kb += size_kb;
-                        }
-                    }
+                if self.logging() {
+                    info!("Excluding: {}", src_path.display());
                 }
+                continue;
             }
-        } else {
-            invalid_count += 1;
+
+            // The path filter describes the tracked tree, not snapshotted link targets
+            if let Some(only) = &self.options.only_paths
+                && self.followed_links == 0
+                && !only.contains(&rel_path)
+            {
+                if self.logging() {
+                    debug!("Skipping untracked: {}", src_path.display());
+                }
+                continue;
+            }
+
+            let result = if file_type.is_symlink() {
+                self.copy_symlink(&src_path, &dst_path, &rel_path)
+            } else if file_type.is_dir() {
+                if self.logging() {
+                    debug!("Copying directory: {}", src_path.display());
+                }
+                self.ancestors.push(canonical);
+                let result = self.copy_dir(&src_path, &dst_path, &rel_path);
+                self.ancestors.pop();
+                result
+            } else {
+                if self.logging() {
+                    debug!("Copying file: {}", src_path.display());
+                }
+                self.copy_file(&src_path, &dst_path, &rel_path)
+            };
+            self.tolerate(&src_path, result)?;
         }
+
+        Ok(())
     }
 
-    println!("Valid Checkpoints: {}", valid_count);
-    println!("Invalid Checkpoints: {}", invalid_count);
-    println!("Total Size: {}", format_size_kb(total_size_kb));
+    /// Passes `result` on, except that with `skip_errors` a failure is only
+    /// recorded against `path`
+    fn tolerate(&mut self, path: &Path, result: Result<()>) -> Result<()> {
+        match result {
+            Err(e) if self.options.skip_errors => {
+                if self.logging() {
+                    debug!("Leaving out {}: {:#}", path.display(), e);
+                }
+                self.stats
+                    .failed
+                    .push((path.to_path_buf(), format!("{:#}", e)));
+                Ok(())
+            }
+            result => result,
+        }
+    }
 
-    println!("\nCheckpoint Details:");
-    for (name, checkpoint) in &config.checkpoints {
-        let checkpoint_path = alts_dir.join(Path::new(name));
-        let exists = checkpoint_path.exists();
-        let status = if exists { "Valid" } else { "Missing" };
-        let size = if exists {
-            match get_dir_size_kb(&checkpoint_path) {
-                Ok(size_kb) => format_size_kb(size_kb),
-                Err(_) => "Unknown".to_string(),
+    fn copy_file(&mut self, src: &Path, dst: &Path, rel: &Path) -> Result<()> {
+        if let Some(max) = self.options.max_file_size {
+            let size = fs::metadata(src)
+                .context("Failed to read file metadata")?
+                .len();
+            if size > max {
+                self.stats.oversized.push((rel.to_path_buf(), size));
+                return Ok(());
             }
+        }
+        if !self.options.dry_run {
+            clear_destination(dst)?;
+        }
+        let inode = if self.options.preserve_hardlinks {
+            shared_inode(&fs::metadata(src).context("Failed to read file metadata")?)
         } else {
-            "N/A".to_string()
+            None
         };
-        println!("  - Name: {}", name);
-        println!("    Status: {}", status);
-        println!("    Size: {}", size);
-        println!("    Created: {}", checkpoint.timestamp);
-    }
-
-    Ok(())
-}
+        if let Some(first) = inode.and_then(|key| self.copied_inodes.get(&key)) {
+            if self.logging() {
+                debug!("Linking {} to {}", dst.display(), first.display());
+            }
+            if self.options.dry_run {
+                self.stats.files += 1;
+            } else {
+                self.pending_links.push((first.clone(), dst.to_path_buf()));
+            }
+            return Ok(());
+        }
 
-fn get_dir_size_kb(path: &Path) -> Result<u64> {
-    if !path.exists() {
-        return Ok(0);
+        let previous = self
+            .options
+            .link_unchanged_from
+            .as_ref()
+            .map(|base| base.join(rel))
+            .filter(|previous| is_unchanged_copy(src, previous));
+        if let Some(previous) = previous {
+            if self.logging() {
+                debug!("Linking unchanged {}", dst.display());
+            }
+            self.stats.linked += 1;
+            if self.options.dry_run {
+                self.stats.files += 1;
+            } else {
+                self.pending_links.push((previous, dst.to_path_buf()));
+            }
+        } else if self.options.dry_run {
+            self.stats.files += 1;
+            self.stats.bytes += fs::metadata(src)
+                .context("Failed to read file metadata")?
+                .len();
+        } else {
+            self.pending_files
+                .push((src.to_path_buf(), dst.to_path_buf()));
+        }
+        if let Some(key) = inode {
+            self.copied_inodes.insert(key, dst.to_path_buf());
+        }
+        Ok(())
     }
 
-    let output = std::process::Command::new("du")
-        .args(["-sk", path.to_str().unwrap()])
-        .output()
-        .context("Failed to execute du command")?;
+    fn copy_symlink(&mut self, src_path: &Path, dst_path: &Path, rel: &Path) -> Result<()> {
+        let link_target = fs::read_link(src_path).context("Failed to read symlink")?;
+        // A dangling link canonicalizes to an error; it can only be recreated as-is
+        let resolved = src_path.canonicalize().ok();
 
-    if !output.status.success() {
-        return Err(anyhow::anyhow!("du command failed"));
-    }
+        let followed = resolved.as_ref().filter(|r| {
+            let into_repository = self
+                .repository
+                .as_ref()
+                .is_some_and(|repo| r.starts_with(repo));
+            !into_repository
+                && (self.options.dereference
+                    || (self.options.snapshot_symlink_targets && !r.starts_with(&self.root)))
+        });
+        if let Some(resolved) = followed {
+            if resolved.is_dir() {
+                // Descending into a directory that contains one being copied would never end
+                if self.ancestors.iter().any(|a| a.starts_with(resolved)) {
+                    if self.logging() {
+                        warn!(
+                            "Symlink cycle detected at {}, recreating as link",
+                            src_path.display()
+                        );
+                    }
+                } else {
+                    if self.logging() {
+                        info!(
+                            "Snapshotting symlink target: {} -> {}",
+                            src_path.display(),
+                            resolved.display()
+                        );
+                    }
+                    self.ancestors.push(resolved.clone());
+                    self.followed_links += 1;
+                    let result = self.copy_dir(resolved, dst_path, rel);
+                    self.followed_links -= 1;
+                    self.ancestors.pop();
+                    return result;
+                }
+            } else {
+                if self.logging() {
+                    info!(
+                        "Snapshotting symlink target: {} -> {}",
+                        src_path.display(),
+                        resolved.display()
+                    );
+                }
+                return self
+                    .copy_file(resolved, dst_path, rel)
+                    .context("Failed to copy symlink target");
+            }
+        }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    if let Some(size_str) = stdout.split_whitespace().next() {
-        match size_str.parse::<u64>() {
-            Ok(size_kb) => Ok(size_kb),
-            Err(_) => Err(anyhow::anyhow!("Failed to parse size from du output")),
+        if self.logging() {
+            info!(
+                "Recreating symlink: {} -> {}",
+                src_path.display(),
+                link_target.display()
+            );
         }
-    } else {
-        Err(anyhow::anyhow!("du command returned unexpected output"))
+        if !self.options.dry_run {
+            clear_destination(dst_path)?;
+            let is_dir = resolved.as_ref().is_some_and(|r| r.is_dir());
+            create_symlink(&link_target, dst_path, is_dir).context("Failed to create symlink")?;
+        }
+        Ok(())
     }
 }
 
-fn format_size_kb(kb: u64) -> String {
-    const UNITS: &[&str] = &["KB", "MB", "GB", "TB"];
-    let mut size = kb as f64;
-    let mut unit_index = 0;
+/// Whether `previous` is a regular file with the size, mtime and mode of
+/// `src`, so it can stand in for a fresh copy
+fn is_unchanged_copy(src: &Path, previous: &Path) -> bool {
+    let (Ok(src), Ok(previous)) = (fs::metadata(src), fs::symlink_metadata(previous)) else {
+        return false;
+    };
+    previous.is_file()
+        && previous.len() == src.len()
+        && previous.modified().ok() == src.modified().ok()
+        && unix_mode(&previous) == unix_mode(&src)
+}
 
-    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
-        size /= 1024.0;
-        unit_index += 1;
+/// Removes a file or symlink already at `dst`, so copying into a directory
+/// that is not empty replaces it instead of failing or writing through a link
+fn clear_destination(dst: &Path) -> Result<()> {
+    match fs::symlink_metadata(dst) {
+        Ok(metadata) if !metadata.is_dir() => {
+            fs::remove_file(dst).with_context(|| format!("Failed to replace '{}'", dst.display()))
+        }
+        _ => Ok(()),
     }
-
-    format!("{:.2} {}", size, UNITS[unit_index])
 }
 
-fn prune() -> Result<()> {
-    let mut config = load_config()?;
-    let current_dir = std::env::current_dir()?;
-    let alts_dir = current_dir.join(ALTS_DIR);
-
-    if config.checkpoints.is_empty() {
-        info!("No checkpoints to prune");
-        return Ok(());
-    }
+/// Device and inode of a file that has more than one hard link
+#[cfg(unix)]
+fn shared_inode(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
 
-    info!("Checking checkpoints...");
-    let mut to_remove: Vec<String> = Vec::new();
+    (metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino()))
+}
 
-    for name in config.checkpoints.keys() {
-        let checkpoint_path = alts_dir.join(Path::new(name));
-        if checkpoint_path.exists() {
-            info!("  Found: {}", name);
-        } else {
-            info!("  Not found: {}", name);
-            to_remove.push(name.clone());
-        }
-    }
+#[cfg(not(unix))]
+fn shared_inode(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
 
-    // Remove unfound checkpoints from index
-    for name in &to_remove {
-        config.checkpoints.remove(name);
-    }
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path, _is_dir: bool) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
 
-    if !to_remove.is_empty() {
-        save_config(&config)?;
-        info!(
-            "Removed {} unfound checkpoint(s) from index",
-            to_remove.len()
-        );
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path, is_dir: bool) -> std::io::Result<()> {
+    if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
     } else {
-        info!("All checkpoints found, nothing to remove");
+        std::os::windows::fs::symlink_file(target, link)
     }
-
-    Ok(())
 }
 
-fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
-    fs::create_dir_all(dst).context("Failed to create directory")?;
+fn main() -> ExitCode {
+    let cli = Cli::parse();
 
-    for entry in fs::read_dir(src).context("Failed to read directory")? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
+    // `RUST_LOG` may refine the default level per module; the flags override it
+    let mut logger = env_logger::Builder::new();
+    logger.filter_level(log::LevelFilter::Info);
+    if let Ok(filters) = std::env::var("RUST_LOG") {
+        logger.parse_filters(&filters);
+    }
+    if cli.quiet {
+        logger.filter_level(log::LevelFilter::Error);
+    } else if cli.verbose {
+        logger.filter_level(log::LevelFilter::Debug);
+    }
+    logger.init();
 
-        if file_type.is_dir() {
-            info!("Copying directory: {}", src_path.display());
-            copy_dir_recursive(&src_path, &dst_path)?;
-        } else {
-            info!("Copying file: {}", src_path.display());
-            fs::copy(&src_path, &dst_path).context("Failed to copy file")?;
+    match run_command(cli) {
+        Ok(code) => code,
+        Err(e) => {
+            error!("{}", e);
+            match FailureKind::of(&e) {
+                Some(kind) => {
+                    debug!("Exiting with code {} ({})", kind.exit_code(), kind);
+                    ExitCode::from(kind.exit_code())
+                }
+                None => ExitCode::from(ERROR_EXIT_CODE),
+            }
         }
     }
-
-    Ok(())
 }
 
-fn main() {
-    env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
-        .init();
+/// Runs the parsed command, leaving the decision on how to exit to `main`
+fn run_command(cli: Cli) -> Result<ExitCode> {
+    let time_zone = cli.time_zone;
 
-    let cli = Cli::parse();
+    if let Some(repo) = &cli.repo {
+        let dir = std::path::absolute(repo)
+            .with_context(|| format!("Invalid repository path '{}'", repo.display()))?;
+        if !dir.is_dir() {
+            return Err(anyhow::anyhow!(
+                "Repository directory '{}' does not exist",
+                repo.display()
+            ));
+        }
+        let _ = REPO_DIR.set(dir);
+    }
+
+    if let Some(jobs) = cli.jobs {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .map_err(|e| anyhow::anyhow!("Failed to start {} worker thread(s): {}", jobs, e))?;
+    }
 
     match cli.command {
-        Commands::Init { dir_name } => {
-            if let Err(e) = init(&dir_name) {
-                error!("{}", e);
-                std::process::exit(1);
+        Commands::Init {
+            dir_name,
+            timestamp_format,
+            allow_outside,
+            nested,
+            checkpoint: first,
+        } => {
+            init(&dir_name, timestamp_format, allow_outside, nested)?;
+            if let Some(name) = first {
+                first_checkpoint(name)?;
             }
         }
-        Commands::Checkpoint { name } => {
-            if let Err(e) = checkpoint(name) {
-                error!("{}", e);
-                std::process::exit(1);
+        Commands::Checkpoint(args) => {
+            if args.output_name_only {
+                log::set_max_level(log::LevelFilter::Error);
+            }
+            let name = checkpoint(&args)?;
+            if args.output_name_only {
+                println!("{}", name);
             }
         }
-        Commands::List { time_order, reverse } => {
-            if let Err(e) = list(time_order, reverse) {
-                error!("{}", e);
-                std::process::exit(1);
+        Commands::List(args) => {
+            if args.json {
+                log::set_max_level(log::LevelFilter::Warn);
             }
+            list(&args, time_zone)?;
+            check_checkpoint_age(&args.age)?;
         }
-        Commands::Prune => {
-            if let Err(e) = prune() {
-                error!("{}", e);
-                std::process::exit(1);
+        Commands::Prune(args) => prune(&args, time_zone)?,
+        Commands::Rename { old, new } => rename(&old, &new)?,
+        Commands::Delete {
+            mut names,
+            mut force,
+            select,
+        } => {
+            if select.is_given() {
+                names = select_checkpoints(&load_config()?, &select, time_zone)?;
+                if names.is_empty() {
+                    info!("No checkpoints match");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                if !force && !confirm(&format!("Delete these {} checkpoint(s)?", names.len()))? {
+                    return Err(anyhow::anyhow!(
+                        "Aborted; nothing was deleted. Re-run interactively or pass --force"
+                    ));
+                }
+                // Already confirmed, so the configured thresholds do not ask again
+                force = true;
+            } else if names.is_empty() {
+                names.push(pick_checkpoint(&load_config()?, "delete")?);
             }
+            delete(&names, force)?
         }
-        Commands::Info => {
-            if let Err(e) = info() {
-                error!("{}", e);
-                std::process::exit(1);
+        Commands::Info {
+            unit,
+            watch,
+            interval,
+            json,
+            age,
+        } => {
+            if json {
+                log::set_max_level(log::LevelFilter::Warn);
+                info_json()?;
+                check_checkpoint_age(&age)?;
+            } else if watch {
+                info_watch(unit, time_zone, interval)?;
+            } else {
+                info(unit, time_zone, &mut BTreeMap::new())?;
+                check_checkpoint_age(&age)?;
             }
         }
+        Commands::Watch {
+            interval,
+            on_change,
+        } => watch(interval, on_change.as_deref())?,
+        Commands::Restore {
+            name,
+            only_changed,
+            verify_after,
+            to_checkpoint_time,
+            into,
+            force,
+        } => restore(
+            &match name {
+                Some(name) => name,
+                None => pick_checkpoint(&load_config()?, "restore")?,
+            },
+            only_changed,
+            verify_after,
+            to_checkpoint_time,
+            into.as_deref(),
+            force,
+        )?,
+        Commands::Impact { name, unit } => impact(&name, unit)?,
+        Commands::Diff {
+            name,
+            other,
+            external,
+            porcelain,
+        } => diff(&name, other.as_deref(), external.as_deref(), porcelain)?,
+        Commands::Run {
+            on_failure,
+            name,
+            message,
+            cmd,
+        } => {
+            let code = run(&cmd, on_failure, name, message)?;
+            return Ok(ExitCode::from(u8::try_from(code).unwrap_or(1)));
+        }
+        Commands::Migrate => migrate()?,
+        Commands::Fsck => fsck()?,
+        Commands::Schema { output } => print_schema(output)?,
+        Commands::CompareRepos { other } => compare_repos(&other)?,
+        Commands::Alias { command } => match command {
+            AliasCommands::Set { label, checkpoint } => alias_set(&label, &checkpoint)?,
+            AliasCommands::Rm { label } => alias_rm(&label)?,
+            AliasCommands::List => alias_list()?,
+        },
+        Commands::Config { command } => match command {
+            ConfigCommands::Get { key } => config_get(&key)?,
+            ConfigCommands::Set { key, values } => config_set(&key, &values)?,
+            ConfigCommands::Unset { key } => config_unset(&key)?,
+            ConfigCommands::List => config_list()?,
+        },
+        Commands::Debug { command } => match command {
+            DebugCommands::SizeCompare { name } => debug_size_compare(&name)?,
+            DebugCommands::TreeHash { name } => debug_tree_hash(&name)?,
+        },
+        Commands::Stat { name, unit } => stat(&name, unit, time_zone)?,
+        Commands::Status { porcelain } => status(porcelain)?,
+        Commands::Reflog => reflog(time_zone)?,
+        Commands::Log { max_count } => show_log(max_count, time_zone)?,
+        Commands::Verify { name } => verify(name.as_deref())?,
+        Commands::Export {
+            name,
+            file,
+            to_dir,
+            select,
+            relative_to,
+            split,
+        } => match (name, file, to_dir) {
+            (_, _, Some(dir)) => export_selected(&select, &dir, time_zone)?,
+            (Some(name), Some(file), None) => export(&name, &file, relative_to.as_deref(), split)?,
+            _ => unreachable!("clap requires a name and file without --to-dir"),
+        },
+        Commands::Import { file, rename, onto } => import(&file, rename, onto.as_deref())?,
+        Commands::Touch {
+            names,
+            all_valid: _,
+            force,
+        } => touch(&names, force)?,
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint(toml: &str) -> Checkpoint {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn porcelain_line_fields() {
+        let plain = checkpoint("timestamp = \"2024-01-02T03:04:05+00:00\"");
+        assert_eq!(
+            porcelain_line("v1", &plain, true),
+            "ok\tv1\t2024-01-02T03:04:05+00:00\t\t"
+        );
+        assert_eq!(
+            porcelain_line("v1", &plain, false),
+            "missing\tv1\t2024-01-02T03:04:05+00:00\t\t"
+        );
+    }
+
+    #[test]
+    fn porcelain_change_line_escapes_path() {
+        assert_eq!(
+            porcelain_change_line("added", Path::new("dir/file.txt")),
+            "added\tdir/file.txt"
+        );
+        assert_eq!(
+            porcelain_change_line("deleted", Path::new("odd\tname\\x")),
+            "deleted\todd\\tname\\\\x"
+        );
+    }
+
+    #[test]
+    fn porcelain_line_escapes_tags_and_message() {
+        let annotated = checkpoint(
+            r#"
+            timestamp = "2024-01-02T03:04:05+00:00"
+            message = "mix down\twith vocals\nsecond line \\ done"
+            tags = ["final mix", "a,b", "tab\there"]
+            "#,
+        );
+        let line = porcelain_line("my song", &annotated, true);
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert_eq!(
+            fields,
+            [
+                "ok",
+                "my song",
+                "2024-01-02T03:04:05+00:00",
+                "final mix,a\\,b,tab\\there",
+                "mix down\\twith vocals\\nsecond line \\\\ done",
+            ]
+        );
+        assert!(!line.contains('\n'));
+    }
+
+    #[test]
+    fn checkpoint_names_are_trimmed() {
+        assert_eq!(validate_checkpoint_name(" foo ").unwrap(), "foo");
+        assert_eq!(validate_checkpoint_name("foo\n").unwrap(), "foo");
+        assert_eq!(validate_checkpoint_name("\tfoo bar  ").unwrap(), "foo bar");
+    }
+
+    #[test]
+    fn checkpoint_names_reject_blank_and_control_characters() {
+        assert!(validate_checkpoint_name("   ").is_err());
+        assert!(validate_checkpoint_name("").is_err());
+        let err = validate_checkpoint_name("a\tb").unwrap_err();
+        assert!(err.to_string().contains("control characters"));
+    }
+
+    /// A fresh empty directory under the system temp dir
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("alts-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes the same small tree into `root`, creating entries in the given
+    /// order
+    fn write_tree(root: &Path, order: impl Iterator<Item = usize>) {
+        for i in order {
+            let dir = root.join(format!("dir{}", i % 4));
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(format!("file{}.txt", i)), format!("content {}", i)).unwrap();
+        }
+    }
+
+    fn hash_with_threads(root: &Path, threads: usize) -> String {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+            .install(|| tree_hash(root))
+            .unwrap()
+    }
+
+    #[test]
+    fn object_storage_keeps_errors_file() {
+        let root = scratch_dir("objects-errors");
+        let checkpoint = root.join("v1");
+        let objects = root.join(OBJECTS_DIR);
+        let restored = root.join("restored");
+        write_tree(&checkpoint, 0..4);
+        fs::write(checkpoint.join(ERRORS_FILE), "secret: Permission denied\n").unwrap();
+        let hash = tree_hash(&checkpoint).unwrap();
+
+        store_objects(&checkpoint, &objects).unwrap();
+        assert!(checkpoint.join(ERRORS_FILE).exists());
+        assert!(checkpoint.join(MANIFEST_FILE).exists());
+
+        restore_objects(&checkpoint, &objects, &restored).unwrap();
+        assert_eq!(
+            fs::read_to_string(restored.join(ERRORS_FILE)).unwrap(),
+            "secret: Permission denied\n"
+        );
+        assert_eq!(tree_hash(&restored).unwrap(), hash);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn json_schema_matches_serialized_fields() {
+        let info = InfoJson {
+            target_dir: "work",
+            target_missing: false,
+            total_checkpoints: 1,
+            valid_checkpoints: 1,
+            invalid_checkpoints: 0,
+            free_space: None,
+            total_size: 3,
+            checkpoints: vec![InfoCheckpointJson {
+                name: "v1",
+                timestamp: "2024-01-02T03:04:05+00:00",
+                exists: true,
+                size: Some(3),
+                message: None,
+                created_by_version: None,
+            }],
+        };
+        let value = serde_json::to_value(&info).unwrap();
+        let schema = InfoJson::json_schema();
+        let keys = |object: &serde_json::Value| -> Vec<String> {
+            object.as_object().unwrap().keys().cloned().collect()
+        };
+        assert_eq!(keys(&value), keys(&schema["properties"]));
+        assert_eq!(
+            keys(&value["checkpoints"][0]),
+            keys(&schema["properties"]["checkpoints"]["items"]["properties"])
+        );
+        assert_eq!(
+            schema["required"].as_array().unwrap().len(),
+            keys(&value).len()
+        );
+        assert_eq!(
+            schema["properties"]["free_space"]["description"],
+            "Bytes; `null` when it cannot be determined"
+        );
+    }
+
+    #[test]
+    fn relative_export_rebuilds_checkpoint() {
+        let root = scratch_dir("relative-export");
+        let (base, new, rebuilt) = (root.join("base"), root.join("new"), root.join("rebuilt"));
+        write_tree(&base, 0..8);
+        write_tree(&new, 0..8);
+        write_tree(&rebuilt, 0..8);
+        fs::write(new.join("dir0/file0.txt"), "changed").unwrap();
+        fs::remove_dir_all(new.join("dir1")).unwrap();
+        fs::remove_file(new.join("dir2/file2.txt")).unwrap();
+        fs::write(new.join("dir2/file2.txt.new"), "added").unwrap();
+        fs::remove_dir_all(new.join("dir3")).unwrap();
+        fs::write(new.join("dir3"), "now a file").unwrap();
+
+        let changes = diff_trees(&base, &new).unwrap();
+        let deleted: Vec<String> = changes
+            .iter()
+            .filter(|change| change.kind == ChangeKind::Removed)
+            .map(|change| change.path.to_string_lossy().into_owned())
+            .collect();
+        let exported = ExportedCheckpoint {
+            name: "new".to_string(),
+            timestamp: String::new(),
+            message: None,
+            tags: Vec::new(),
+            hash: None,
+            created_by_version: None,
+            partial: false,
+            errors: None,
+            deleted: deleted.clone(),
+            base: None,
+        };
+        let archive = root.join("new.tar.gz");
+        write_export(&archive, &exported, &new, Some(&changes)).unwrap();
+
+        let unpacked = root.join("unpacked");
+        fs::create_dir(&unpacked).unwrap();
+        extract_tar(
+            flate2::read::GzDecoder::new(fs::File::open(&archive).unwrap()),
+            &unpacked,
+        )
+        .unwrap();
+        assert!(
+            !unpacked
+                .join(EXPORT_TREE_DIR)
+                .join("dir0/file4.txt")
+                .exists()
+        );
+        apply_export_changes(&unpacked.join(EXPORT_TREE_DIR), &deleted, &rebuilt).unwrap();
+        assert_eq!(tree_hash(&rebuilt).unwrap(), tree_hash(&new).unwrap());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn select_checkpoints_combines_filters() {
+        let config: Config = toml::from_str(
+            r#"
+            target_dir = "work"
+
+            [checkpoints.release-2]
+            timestamp = "2024-03-01T23:30:00-02:00"
+            tags = ["kept"]
+
+            [checkpoints.release-1]
+            timestamp = "2023-12-31T12:00:00+00:00"
+            tags = ["kept", "old"]
+
+            [checkpoints.nightly]
+            timestamp = "2023-06-01T12:00:00+00:00"
+
+            [checkpoints.undated]
+            timestamp = ""
+            "#,
+        )
+        .unwrap();
+        let select = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["alts", "delete"].iter().chain(args)).unwrap();
+            let Commands::Delete { select, .. } = cli.command else {
+                unreachable!()
+            };
+            select_checkpoints(&config, &select, cli.time_zone).unwrap()
+        };
+
+        assert_eq!(
+            select(&["--before", "2024-01-01"]),
+            ["nightly", "release-1"]
+        );
+        assert_eq!(
+            select(&["--between", "2023-12-31", "2024-03-01"]),
+            ["release-1", "release-2"]
+        );
+        // The stored offset puts release-2 on March 1st, UTC on March 2nd
+        assert_eq!(
+            select(&[
+                "--between",
+                "2024-03-02",
+                "2024-03-02",
+                "--time-zone",
+                "utc"
+            ]),
+            ["release-2"]
+        );
+        assert_eq!(
+            select(&["--matching", "release-*", "--tag", "old"]),
+            ["release-1"]
+        );
+        assert_eq!(select(&["--prefix", "n"]), ["nightly"]);
+        assert!(select(&["--prefix", "n", "--tag", "kept"]).is_empty());
+    }
+
+    #[test]
+    fn split_export_round_trips() {
+        let root = scratch_dir("split-export");
+        for (length, parts) in [(0, 1), (10, 1), (30, 3), (31, 4)] {
+            let archive = root.join(format!("{}.tar.gz", length));
+            let content: Vec<u8> = (0..length).map(|i| i as u8).collect();
+            fs::write(&archive, &content).unwrap();
+
+            let manifest = split_export(&archive, 10).unwrap();
+            assert!(!archive.exists());
+            assert!(split_part_path(&archive, parts).is_file());
+            assert!(!split_part_path(&archive, parts + 1).exists());
+
+            let mut joined = Vec::new();
+            open_export(&archive)
+                .unwrap()
+                .read_to_end(&mut joined)
+                .unwrap();
+            assert_eq!(joined, content);
+            assert!(open_export(&manifest).is_ok());
+        }
+
+        fs::write(split_part_path(&root.join("31.tar.gz"), 2), "x").unwrap();
+        assert!(open_export(&root.join("31.tar.gz.parts")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn tree_hash_is_deterministic() {
+        let forward = scratch_dir("hash-forward");
+        let backward = scratch_dir("hash-backward");
+        write_tree(&forward, 0..64);
+        write_tree(&backward, (0..64).rev());
+
+        let single = hash_with_threads(&forward, 1);
+        assert_eq!(single, hash_with_threads(&forward, 8));
+        assert_eq!(single, hash_with_threads(&backward, 1));
+        assert_eq!(single, hash_with_threads(&backward, 8));
+
+        fs::write(forward.join("dir0/file0.txt"), "changed").unwrap();
+        assert_ne!(single, hash_with_threads(&forward, 8));
+
+        fs::remove_dir_all(&forward).unwrap();
+        fs::remove_dir_all(&backward).unwrap();
     }
 }